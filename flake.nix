@@ -27,6 +27,8 @@
 
         satisfactoryServeMap = craneLib.buildPackage {
           src = craneLib.cleanCargoSource (craneLib.path ./.);
+          # The source has no .git, pass the commit for /version
+          SERVE_MAP_GIT_COMMIT = self.shortRev or self.dirtyShortRev or "";
           buildInputs = with pkgs; [
             openssl
             pkg-config
@@ -0,0 +1,116 @@
+//! Optional access log in the Apache Combined Log Format.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::{client_ip, probe_filter};
+
+/// Size of a save whose body is compressed on the fly, which has no size of its own to log
+pub struct SaveBytes(pub Option<u64>);
+
+/// Appends a line for every response to the access log file
+pub struct AccessLog {
+    file: Mutex<File>,
+}
+
+impl AccessLog {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open access log {}", path))?;
+
+        Ok(AccessLog {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Write access log",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Streamed bodies have no known size, partial ones set the header and compressed saves
+        // are logged with the size of the save
+        let bytes = match response.body_mut().size().await {
+            Some(size) => Some(size as u64),
+            None => response
+                .headers()
+                .get_one("Content-Length")
+                .and_then(|len| len.parse().ok())
+                .or(request.local_cache(|| SaveBytes(None)).0),
+        };
+
+        let timestamp = OffsetDateTime::now_utc()
+            .format(format_description!(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+            ))
+            .unwrap_or_default();
+
+        let line = format!(
+            // Rocket doesn't expose the protocol version of the request
+            "{} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\"\n",
+            client_ip::client_ip(request)
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            timestamp,
+            // Rejected probes are logged as they were sent, not as the path they were rewritten to
+            probe_filter::rejected(request).map_or(request.method(), |rejected| rejected.method),
+            request_target(request),
+            response.status().code,
+            bytes
+                .map(|bytes| bytes.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            quoted(request.headers().get_one("Referer")),
+            quoted(request.headers().get_one("User-Agent")),
+        );
+
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            error!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+// The path and query of the request with the access token left out
+fn request_target(request: &Request<'_>) -> String {
+    let path = match probe_filter::rejected(request) {
+        Some(rejected) => rejected.path.clone(),
+        None => request.uri().path().to_string(),
+    };
+    let Some(query) = request.uri().query() else {
+        return path;
+    };
+
+    let query: Vec<_> = query
+        .as_str()
+        .split('&')
+        .filter(|param| param.split('=').next() != Some("token"))
+        .collect();
+    if query.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, query.join("&"))
+    }
+}
+
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) => value.replace('\\', "\\\\").replace('"', "\\\""),
+        None => "-".to_string(),
+    }
+}
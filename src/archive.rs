@@ -0,0 +1,95 @@
+//! Streams all versions of a save as a zip archive.
+//!
+//! Saves are already compressed, so entries are stored as is. The archive is written on
+//! a blocking thread into a pipe that is streamed to the client, so it never has to fit
+//! into memory.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use rocket::http::{ContentType, Header};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio;
+use rocket::Request;
+use tokio_util::io::SyncIoBridge;
+use tracing::error;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// Size of the pipe between the zip writer and the response body
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+pub struct SaveArchive {
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl<'r> Responder<'r, 'static> for SaveArchive {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let (reader, writer) = tokio::io::duplex(PIPE_CAPACITY);
+        let writer = SyncIoBridge::new(writer);
+        let name = self.name.clone();
+
+        tokio::task::spawn_blocking(move || {
+            // Errors can't change the response status anymore, the client sees a truncated zip
+            if let Err(e) = write_archive(writer, &self.paths) {
+                error!("Failed to write archive for {}: {:#}", name, e);
+            }
+        });
+
+        Response::build()
+            .header(ContentType::ZIP)
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.zip\"", self.name),
+            ))
+            .streamed_body(reader)
+            .ok()
+    }
+}
+
+fn write_archive(writer: impl Write, paths: &[PathBuf]) -> Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+    let mut entry_names = HashSet::new();
+
+    for path in paths {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(size >= u32::MAX as u64);
+        zip.start_file(entry_name(path, &mut entry_names), options)?;
+        io::copy(&mut file, &mut zip)
+            .with_context(|| format!("Failed to add {} to the archive", path.display()))?;
+    }
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+// The file name, prefixed with its directory when several save directories contain the same name
+fn entry_name(path: &std::path::Path, entry_names: &mut HashSet<String>) -> String {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = if entry_names.contains(&file_name) {
+        let dir = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        format!("{}/{}", dir, file_name)
+    } else {
+        file_name
+    };
+
+    entry_names.insert(name.clone());
+    name
+}
@@ -0,0 +1,46 @@
+//! Optional bearer token authentication for save endpoints.
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::ServerConfig;
+
+/// Request guard that succeeds when no access token is configured, or when the
+/// request carries the token either as `Authorization: Bearer <token>` or as a
+/// `?token=` query parameter for clients that can't set headers.
+pub struct Authorized;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<ServerConfig>() else {
+            return Outcome::Error((Status::InternalServerError, "Missing server config"));
+        };
+
+        let Some(access_token) = &config.access_token else {
+            return Outcome::Success(Authorized);
+        };
+
+        let header_token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let query_token = request.query_value::<&str>("token").and_then(Result::ok);
+
+        match header_token.or(query_token) {
+            Some(token) if constant_time_eq(token.as_bytes(), access_token.as_bytes()) => {
+                Outcome::Success(Authorized)
+            }
+            _ => Outcome::Error((Status::Unauthorized, "Missing or invalid access token")),
+        }
+    }
+}
+
+// Compares without short circuiting so the token can't be guessed by timing responses
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
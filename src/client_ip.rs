@@ -0,0 +1,48 @@
+//! Client IP of a request, taken from `X-Forwarded-For` only when a trusted proxy sent it.
+
+use std::net::{IpAddr, SocketAddr};
+
+use rocket::Request;
+
+use crate::ServerConfig;
+
+/// The IP of the client, `None` when the peer isn't known, e.g. in local tests.
+/// Unlike `Request::client_ip`, headers from other peers are ignored so they can't be spoofed.
+pub fn client_ip(request: &Request<'_>) -> Option<IpAddr> {
+    let peer = request.remote()?.ip().to_canonical();
+    let trusted = request
+        .rocket()
+        .state::<ServerConfig>()
+        .map(|config| config.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+
+    // Every proxy appends the address it got the request from, so the client is the last one
+    // that isn't a trusted proxy. Anything before it may have been made up by the client.
+    let forwarded: Vec<_> = request
+        .headers()
+        .get("X-Forwarded-For")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    for address in forwarded.iter().rev() {
+        match parse_address(address) {
+            Some(ip) if trusted.contains(&ip) => continue,
+            Some(ip) => return Some(ip),
+            None => break,
+        }
+    }
+
+    Some(peer)
+}
+
+// Proxies may include the port, e.g. `203.0.113.7:51234` or `[2001:db8::1]:51234`
+fn parse_address(address: &str) -> Option<IpAddr> {
+    let ip = match address.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => address.parse::<SocketAddr>().ok()?.ip(),
+    };
+    Some(ip.to_canonical())
+}
@@ -0,0 +1,164 @@
+//! Optional watcher that reloads the options of [`LiveConfig`] when the config file changes.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use tracing::{error, info, warn};
+
+use crate::{Config, LiveConfig, CONFIG_FILES};
+
+/// Starts watching the config files on liftoff and stops on shutdown
+pub struct ConfigWatcher {
+    // The --config argument, reloaded instead of the default files when given
+    config_path: Option<String>,
+    config_files: Vec<PathBuf>,
+    live: Arc<RwLock<LiveConfig>>,
+    // Options that only apply after a restart, as last seen in the config file
+    restart_options: Arc<Mutex<Vec<(&'static str, String)>>>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        config_path: Option<String>,
+        config: &Config,
+        live: Arc<RwLock<LiveConfig>>,
+    ) -> Self {
+        let config_files = match &config_path {
+            Some(path) => vec![PathBuf::from(path)],
+            None => CONFIG_FILES.iter().map(PathBuf::from).collect(),
+        };
+        ConfigWatcher {
+            config_path,
+            config_files,
+            live,
+            restart_options: Arc::new(Mutex::new(restart_options(config))),
+            watcher: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ConfigWatcher {
+    fn info(&self) -> Info {
+        Info {
+            name: "Watch config file",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, _rocket: &Rocket<Orbit>) {
+        let config_path = self.config_path.clone();
+        let config_files = self.config_files.clone();
+        let live = self.live.clone();
+        let restart_options = self.restart_options.clone();
+
+        let handler = move |result: notify::Result<Event>| match result {
+            Ok(event) => handle_event(
+                event,
+                config_path.as_deref(),
+                &config_files,
+                &live,
+                &restart_options,
+            ),
+            Err(e) => error!("Config watcher error: {}", e),
+        };
+
+        let mut watcher = match notify::recommended_watcher(handler) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to start config watcher: {}", e);
+                return;
+            }
+        };
+
+        // Editors often replace the file instead of writing to it, so watch the directory
+        let dirs: BTreeSet<_> = self
+            .config_files
+            .iter()
+            .map(|path| match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            })
+            .collect();
+        for dir in dirs {
+            match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => info!("Watching {} for config changes", dir.display()),
+                Err(e) => error!("Failed to watch {}: {}", dir.display(), e),
+            }
+        }
+
+        *self.watcher.lock().unwrap() = Some(watcher);
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        // Dropping the watcher stops it
+        if self.watcher.lock().unwrap().take().is_some() {
+            info!("Stopped watching config file");
+        }
+    }
+}
+
+fn handle_event(
+    event: Event,
+    config_path: Option<&str>,
+    config_files: &[PathBuf],
+    live: &RwLock<LiveConfig>,
+    seen_restart_options: &Mutex<Vec<(&'static str, String)>>,
+) {
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return;
+    }
+    let is_config_file = event.paths.iter().any(|path| {
+        config_files
+            .iter()
+            .any(|config_file| path.file_name() == config_file.file_name())
+    });
+    if !is_config_file {
+        return;
+    }
+
+    // A broken config keeps the running one, so a typo doesn't take the server down
+    let (config, source) = match Config::load(config_path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Failed to reload configuration: {:#}", e);
+            return;
+        }
+    };
+
+    let mut seen_restart_options = seen_restart_options.lock().unwrap();
+    for ((name, old), (_, new)) in seen_restart_options.iter().zip(restart_options(&config)) {
+        if *old != new {
+            warn!(
+                "{} changed from {:?} to {:?}, restart the server to apply it",
+                name, old, new
+            );
+        }
+    }
+    *seen_restart_options = restart_options(&config);
+
+    // Editors trigger several events per save, only log actual changes
+    let reloaded = LiveConfig::from_config(&config);
+    let mut live = live.write().unwrap();
+    if *live != reloaded {
+        *live = reloaded;
+        info!("Reloaded configuration from {}", source);
+    }
+}
+
+fn restart_options(config: &Config) -> Vec<(&'static str, String)> {
+    vec![
+        ("address", config.address.clone()),
+        ("port", config.port.to_string()),
+        ("tls_cert", config.tls_cert.clone().unwrap_or_default()),
+        ("tls_key", config.tls_key.clone().unwrap_or_default()),
+    ]
+}
@@ -0,0 +1,56 @@
+//! SHA-256 hashes of save files, cached by path and modified time so large files are
+//! only hashed again after they change.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+struct CachedHash {
+    modified: SystemTime,
+    size: u64,
+    hash: String,
+}
+
+#[derive(Default)]
+pub struct HashCache {
+    entries: Mutex<HashMap<PathBuf, CachedHash>>,
+}
+
+impl HashCache {
+    /// Returns the hex encoded SHA-256 of the file, hashing it if it changed since last time.
+    /// This reads the whole file, so call it from a blocking thread.
+    pub fn sha256(&self, path: &Path) -> io::Result<String> {
+        let metadata = path.metadata()?;
+        let (modified, size) = (metadata.modified()?, metadata.len());
+
+        if let Some(cached) = self.entries.lock().unwrap().get(path) {
+            if cached.modified == modified && cached.size == size {
+                return Ok(cached.hash.clone());
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        let hash: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        // Replaces the entry of an older version of the file
+        self.entries.lock().unwrap().insert(
+            path.to_path_buf(),
+            CachedHash {
+                modified,
+                size,
+                hash: hash.clone(),
+            },
+        );
+        Ok(hash)
+    }
+}
@@ -0,0 +1,67 @@
+//! Limit for the number of save downloads streamed at the same time.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use rocket::tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use rocket::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Seconds clients are asked to wait before retrying when every slot is taken
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+// Clones share their slots, so profiles count towards the same limit
+#[derive(Clone)]
+pub struct DownloadLimiter {
+    semaphore: Arc<Semaphore>,
+}
+
+impl DownloadLimiter {
+    pub fn new(max_concurrent_downloads: usize) -> Self {
+        DownloadLimiter {
+            semaphore: Arc::new(Semaphore::new(max_concurrent_downloads)),
+        }
+    }
+
+    /// Takes a download slot, `None` when every slot is in use
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.semaphore.clone().try_acquire_owned().ok()
+    }
+}
+
+/// Body that holds on to its download slot until the response is dropped,
+/// which is after the last byte was sent or the client went away
+pub struct Permitted<R> {
+    inner: R,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<R> Permitted<R> {
+    pub fn new(inner: R, permit: Option<OwnedSemaphorePermit>) -> Self {
+        Permitted {
+            inner,
+            _permit: permit,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Permitted<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for Permitted<R> {
+    fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        Pin::new(&mut self.inner).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_complete(cx)
+    }
+}
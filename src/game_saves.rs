@@ -0,0 +1,88 @@
+//! Default save locations of the game, used for `save_dir = "auto"`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+// Steam app id of Satisfactory, the Proton prefix is named after it
+const STEAM_APP_ID: &str = "526870";
+
+/// Directories with saves below the game's save locations, one per player or server.
+/// Only directories containing a file with one of the extensions are returned.
+pub fn save_dirs(extensions: &[String]) -> Result<Vec<String>> {
+    let roots = save_roots();
+
+    let mut dirs = Vec::new();
+    for root in &roots {
+        let Ok(entries) = fs::read_dir(root) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() && contains_saves(&path, extensions) {
+                dirs.push(path.display().to_string());
+            }
+        }
+    }
+    dirs.sort();
+
+    if dirs.is_empty() {
+        let roots: Vec<_> = roots
+            .iter()
+            .map(|root| root.display().to_string())
+            .collect();
+        bail!(
+            "save_dir is \"auto\" but no Satisfactory saves were found in {}, set save_dir to the save directory instead",
+            if roots.is_empty() {
+                "any known location".to_string()
+            } else {
+                roots.join(", ")
+            }
+        );
+    }
+    Ok(dirs)
+}
+
+// The SaveGames directories, which contain a directory per Steam or Epic user id
+fn save_roots() -> Vec<PathBuf> {
+    const SAVE_GAMES: &str = "FactoryGame/Saved/SaveGames";
+
+    if cfg!(windows) {
+        return std::env::var_os("LOCALAPPDATA")
+            .map(|local_app_data| Path::new(&local_app_data).join(SAVE_GAMES))
+            .into_iter()
+            .collect();
+    }
+
+    let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+        return Vec::new();
+    };
+    let proton_local_app_data = format!(
+        "steamapps/compatdata/{}/pfx/drive_c/users/steamuser/AppData/Local",
+        STEAM_APP_ID
+    );
+    vec![
+        // The game running through Proton, in the common Steam install locations
+        home.join(".steam/steam")
+            .join(&proton_local_app_data)
+            .join(SAVE_GAMES),
+        home.join(".local/share/Steam")
+            .join(&proton_local_app_data)
+            .join(SAVE_GAMES),
+        // The Linux dedicated server, which saves to `server`
+        home.join(".config/Epic").join(SAVE_GAMES),
+    ]
+}
+
+fn contains_saves(dir: &Path, extensions: &[String]) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let file_name = entry.file_name().to_string_lossy().to_lowercase();
+        extensions
+            .iter()
+            .any(|extension| file_name.ends_with(&format!(".{}", extension.to_lowercase())))
+    })
+}
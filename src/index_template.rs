@@ -0,0 +1,210 @@
+//! Minimal template rendering for the `/map` index page.
+//!
+//! Templates are plain HTML with `{{placeholder}}` markers. The part between
+//! `{{#saves}}` and `{{/saves}}` is repeated for every save. Available placeholders:
+//!
+//! - Page: `{{title}}`, `{{total_files}}`, `{{total_size}}`, `{{generated_at}}` with the
+//!   server time the page was rendered at, `{{refresh}}` with a refresh meta tag when
+//!   `index_refresh_secs` is set, `{{message}}` with the `index_message` banner and
+//!   `{{saves}}`, which renders the save list items of the built-in template
+//! - Inside the saves loop: `{{name}}`, `{{url}}`, `{{file_count}}`, `{{size}}`,
+//!   `{{play_time}}`, which is empty when the header of the latest save can't be read, and
+//!   `{{warning}}`, which is only set when a save has more files than the warn threshold
+//!
+//! Unknown placeholders are left untouched.
+//!
+//! Error pages for browsers use the built-in `error.html` with `{{status}}`, `{{message}}`
+//! and `{{index_url}}`. The `/admin` page uses the built-in `admin.html`.
+
+pub const DEFAULT_TEMPLATE: &str = include_str!("index.html");
+const ERROR_TEMPLATE: &str = include_str!("error.html");
+const ADMIN_TEMPLATE: &str = include_str!("admin.html");
+
+const LOOP_START: &str = "{{#saves}}";
+const LOOP_END: &str = "{{/saves}}";
+
+pub struct IndexEntry {
+    pub name: String,
+    pub url: String,
+    pub file_count: String,
+    pub size: String,
+    pub play_time: String,
+    pub warning: String,
+}
+
+impl IndexEntry {
+    fn value(&self, key: &str) -> Option<String> {
+        match key {
+            "name" => Some(escape_html(&self.name)),
+            "url" => Some(escape_html(&self.url)),
+            "file_count" => Some(escape_html(&self.file_count)),
+            "size" => Some(escape_html(&self.size)),
+            "play_time" => Some(escape_html(&self.play_time)),
+            "warning" => Some(escape_html(&self.warning)),
+            _ => None,
+        }
+    }
+}
+
+pub struct IndexPage {
+    pub title: String,
+    pub entries: Vec<IndexEntry>,
+    pub total_files: String,
+    pub total_size: String,
+    pub generated_at: String,
+    pub refresh_secs: Option<u64>,
+    pub message: Option<String>,
+}
+
+impl IndexPage {
+    fn value(&self, key: &str) -> Option<String> {
+        match key {
+            "title" => Some(escape_html(&self.title)),
+            "total_files" => Some(escape_html(&self.total_files)),
+            "total_size" => Some(escape_html(&self.total_size)),
+            "generated_at" => Some(escape_html(&self.generated_at)),
+            "refresh" => Some(
+                self.refresh_secs
+                    .map(|secs| format!("<meta http-equiv=\"refresh\" content=\"{}\">", secs))
+                    .unwrap_or_default(),
+            ),
+            "message" => Some(
+                self.message
+                    .as_ref()
+                    .map(|message| format!("<p class=\"message\">{}</p>", escape_html(message)))
+                    .unwrap_or_default(),
+            ),
+            "saves" => Some(self.default_items()),
+            _ => None,
+        }
+    }
+
+    // The save list items as rendered by the built-in template
+    fn default_items(&self) -> String {
+        let start = DEFAULT_TEMPLATE.find(LOOP_START).unwrap_or(0) + LOOP_START.len();
+        let end = DEFAULT_TEMPLATE.find(LOOP_END).unwrap_or(start);
+        let body = &DEFAULT_TEMPLATE[start..end];
+
+        self.entries
+            .iter()
+            .map(|entry| fill(body, |key| entry.value(key)))
+            .collect()
+    }
+}
+
+pub fn render(template: &str, page: &IndexPage) -> String {
+    let mut html = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(LOOP_START) {
+        let Some(len) = rest[start..].find(LOOP_END) else {
+            break;
+        };
+        let body = &rest[start + LOOP_START.len()..start + len];
+
+        html.push_str(&fill(&rest[..start], |key| page.value(key)));
+        for entry in &page.entries {
+            html.push_str(&fill(body, |key| entry.value(key)));
+        }
+
+        rest = &rest[start + len + LOOP_END.len()..];
+    }
+
+    html.push_str(&fill(rest, |key| page.value(key)));
+    html
+}
+
+pub fn render_error(status: &str, message: &str, index_url: &str) -> String {
+    fill(ERROR_TEMPLATE, |key| match key {
+        "status" => Some(escape_html(status)),
+        "message" => Some(escape_html(message)),
+        "index_url" => Some(escape_html(index_url)),
+        _ => None,
+    })
+}
+
+pub struct AdminPage {
+    pub title: String,
+    pub save_dirs: Vec<String>,
+    pub save_count: usize,
+    pub total_files: String,
+    pub total_size: String,
+    pub downloads: u64,
+    pub bytes_served: String,
+    pub uptime: String,
+    pub generated_at: String,
+    // Name, size and time of each download, newest first
+    pub recent_downloads: Vec<(String, String, String)>,
+}
+
+pub fn render_admin(page: &AdminPage) -> String {
+    fill(ADMIN_TEMPLATE, |key| match key {
+        "title" => Some(escape_html(&page.title)),
+        "save_dirs" => Some(escape_html(&page.save_dirs.join(", "))),
+        "save_count" => Some(page.save_count.to_string()),
+        "total_files" => Some(escape_html(&page.total_files)),
+        "total_size" => Some(escape_html(&page.total_size)),
+        "downloads" => Some(page.downloads.to_string()),
+        "bytes_served" => Some(escape_html(&page.bytes_served)),
+        "uptime" => Some(escape_html(&page.uptime)),
+        "generated_at" => Some(escape_html(&page.generated_at)),
+        "recent_downloads" if page.recent_downloads.is_empty() => {
+            Some("<p class=\"empty\">No downloads since the server started</p>".to_string())
+        }
+        "recent_downloads" => {
+            let rows: String = page
+                .recent_downloads
+                .iter()
+                .map(|(name, size, at)| {
+                    format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        escape_html(name),
+                        escape_html(size),
+                        escape_html(at)
+                    )
+                })
+                .collect();
+            Some(format!("<table>{}</table>", rows))
+        }
+        _ => None,
+    })
+}
+
+// Replaces placeholders in a single pass, so substituted values are never expanded again
+fn fill(text: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 2];
+
+        filled.push_str(&rest[..start]);
+        match value(placeholder[2..placeholder.len() - 2].trim()) {
+            Some(value) => filled.push_str(&value),
+            None => filled.push_str(placeholder),
+        }
+
+        rest = &rest[start + len + 2..];
+    }
+
+    filled.push_str(rest);
+    filled
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,97 @@
+//! Leveled logging with a span per HTTP request.
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Context, Result};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use serde::Deserialize;
+use tracing::{debug, info, info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::client_ip;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors like Loki or Elasticsearch
+    Json,
+}
+
+/// Installs the global subscriber, `log_level` accepts any `RUST_LOG` style filter
+pub fn init(log_level: &str, format: LogFormat) -> Result<()> {
+    let filter = EnvFilter::try_new(log_level)
+        .with_context(|| format!("Invalid log level {:?}", log_level))?;
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.try_init(),
+        // Event fields like `save_name` become top-level keys, the request fields are in `span`
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    }
+    .map_err(|e| anyhow!(e))
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The request span, created on first use and cached for the rest of the request
+pub fn request_span(request: &Request<'_>) -> Span {
+    request
+        .local_cache(|| {
+            info_span!(
+                "request",
+                id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+                method = %request.method(),
+                // Only the path, the query may carry an access token
+                path = %request.uri().path(),
+                client_ip = %client_ip::client_ip(request)
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_default(),
+            )
+        })
+        .clone()
+}
+
+/// Request guard giving handlers access to the span of the current request
+pub struct RequestSpan(pub Span);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestSpan {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestSpan(request_span(request)))
+    }
+}
+
+/// Logs every request and its response status inside the request span
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Log requests",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request_span(request).in_scope(|| debug!("Request received"));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        request_span(request)
+            .in_scope(|| info!(status = response.status().code, "Request completed"));
+    }
+}
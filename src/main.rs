@@ -1,18 +1,69 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use glob::glob;
-use rocket::fairing::{Fairing, Info, Kind};
-use rocket::fs::NamedFile;
-use rocket::http::Header;
+use access_log::AccessLog;
+use anyhow::{bail, Context, Result};
+use archive::SaveArchive;
+use auth::Authorized;
+use config_watcher::ConfigWatcher;
+use content_hash::HashCache;
+use download_limit::DownloadLimiter;
+use index_template::{AdminPage, IndexEntry, IndexPage};
+use logging::{LogFormat, RequestLogger, RequestSpan};
+use metrics::Metrics;
+use probe_filter::{ProbeFilter, Rejected};
+use profile::Profiles;
+use rate_limit::{RateLimited, RateLimiter, TooManyRequests};
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::fs::{FileServer, NamedFile};
+use rocket::http::uri::{Absolute, Origin, Reference};
+use rocket::http::{Accept, ContentType, Header, Method, RawStr, Status};
 use rocket::response::content::RawHtml;
-use rocket::response::Response;
-use rocket::Request;
+use rocket::response::{self, Redirect, Responder, Response};
+use rocket::serde::json::Json;
 use rocket::State;
-use serde::Deserialize;
+use rocket::{Build, Either, Request, Rocket, Route};
+use sav_body::SaveObjects;
+use sav_header::SaveHeader;
+use save_cache::SaveCache;
+use save_response::{AcceptEncoding, Conditional, Encoding, SaveFile, SaveResponse};
+use save_watcher::SaveWatcher;
+use serde::{Deserialize, Serialize};
+use throttle::DownloadThrottle;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tracing::{debug, error, info, warn};
+mod access_log;
+mod archive;
+mod auth;
+mod client_ip;
+mod config_watcher;
+mod content_hash;
+mod download_limit;
+mod game_saves;
+mod index_template;
+mod logging;
+mod metrics;
+mod probe_filter;
+mod profile;
+mod rate_limit;
 mod rocket_anyhow;
+mod sav_body;
+mod sav_header;
+mod save_cache;
+mod save_response;
+mod save_watcher;
+mod throttle;
+
+#[cfg(test)]
+mod tests;
 
 #[macro_use]
 extern crate rocket;
@@ -21,24 +72,470 @@ extern crate rocket;
 #[derive(Deserialize, Debug)]
 struct Config {
     base_url: String,
-    save_dir: String,
+    save_dir: Option<String>,
+    #[serde(default)]
+    save_dirs: Vec<String>,
     port: u16,
+    #[serde(default)]
+    compress_saves: bool,
+    #[serde(default = "default_cache_ttl_secs")]
+    cache_ttl_secs: u64,
+    #[serde(default = "default_allowed_origins")]
+    allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    allowed_methods: Vec<String>,
+    access_token: Option<String>,
+    #[serde(default = "default_log_level")]
+    log_level: String,
+    #[serde(default)]
+    log_format: LogFormat,
+    #[serde(default = "default_file_extensions")]
+    file_extensions: Vec<String>,
+    #[serde(default)]
+    mime_overrides: HashMap<String, String>,
+    #[serde(default = "default_address")]
+    address: String,
+    #[serde(default)]
+    watch_saves: bool,
+    index_template: Option<String>,
+    #[serde(default = "default_index_title")]
+    index_title: String,
+    #[serde(default)]
+    index_sort: IndexSort,
+    max_requests_per_minute: Option<u32>,
+    #[serde(default)]
+    trusted_proxies: Vec<IpAddr>,
+    max_concurrent_downloads: Option<usize>,
+    max_download_bytes_per_sec: Option<u64>,
+    #[serde(default)]
+    split_download_bandwidth: bool,
+    #[serde(default)]
+    parse_saves: bool,
+    #[serde(default = "default_name_suffixes")]
+    name_suffixes: Vec<String>,
+    #[serde(default = "default_name_separator")]
+    name_separator: String,
+    #[serde(default)]
+    hidden_names: Vec<String>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    #[serde(default)]
+    enable_metrics: bool,
+    #[serde(default = "default_map_viewer_url_template")]
+    map_viewer_url_template: String,
+    #[serde(default = "default_shutdown_grace_secs")]
+    shutdown_grace_secs: u32,
+    #[serde(default)]
+    case_insensitive_names: bool,
+    access_log: Option<String>,
+    blueprint_dir: Option<String>,
+    static_dir: Option<String>,
+    max_file_size_bytes: Option<u64>,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default = "default_enable_index")]
+    enable_index: bool,
+    #[serde(default = "default_root_redirect")]
+    root_redirect: Option<String>,
+    autosave_warn_threshold: Option<usize>,
+    index_refresh_secs: Option<u64>,
+    #[serde(default)]
+    hot_reload: bool,
+    #[serde(default)]
+    startup_selftest: bool,
+    #[serde(default = "default_cache_control")]
+    cache_control: Option<String>,
+    #[serde(default = "default_follow_symlinks")]
+    follow_symlinks: bool,
+    index_message: Option<String>,
+    #[serde(default)]
+    serve_stable_only: bool,
+    #[serde(default = "default_min_age_secs")]
+    min_age_secs: u64,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileConfig>,
+}
+
+// Saves served under their own route prefix, e.g. `/stable/map/<name>`
+#[derive(Deserialize, Debug)]
+struct ProfileConfig {
+    save_dir: Option<String>,
+    #[serde(default)]
+    save_dirs: Vec<String>,
+    index_title: Option<String>,
+}
+
+impl ProfileConfig {
+    fn save_dirs(&self) -> Vec<String> {
+        self.save_dir
+            .iter()
+            .chain(self.save_dirs.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+// First path segments of the top-level routes, a profile with one of these names would collide
+const RESERVED_PROFILE_NAMES: [&str; 12] = [
+    "map",
+    "map.txt",
+    "api",
+    "blueprint",
+    "static",
+    "health",
+    "version",
+    "openapi.json",
+    "admin",
+    "metrics",
+    "favicon.ico",
+    "profiles",
+];
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_file_extensions() -> Vec<String> {
+    vec!["sav".to_string()]
+}
+
+fn default_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_index_title() -> String {
+    "Satisfactory Saves".to_string()
+}
+
+fn default_name_suffixes() -> Vec<String> {
+    vec![
+        "_autosave_{n}".to_string(),
+        "_CalculatorSave".to_string(),
+        "_CalculatorSave{n}".to_string(),
+    ]
+}
+
+fn default_map_viewer_url_template() -> String {
+    "https://satisfactory-calculator.com/en/interactive-map?url={base_url}/map/{name}".to_string()
+}
+
+fn default_root_redirect() -> Option<String> {
+    Some("/map".to_string())
+}
+
+fn default_cache_control() -> Option<String> {
+    // Clients always revalidate with the ETag, which is cheap thanks to 304 responses
+    Some("no-cache".to_string())
+}
+
+fn default_follow_symlinks() -> bool {
+    true
+}
+
+fn default_shutdown_grace_secs() -> u32 {
+    30
+}
+
+fn default_min_age_secs() -> u64 {
+    10
+}
+
+fn default_name_separator() -> String {
+    "_".to_string()
+}
+
+fn default_enable_index() -> bool {
+    true
+}
+
+fn default_allowed_origins() -> Vec<String> {
+    vec!["https://satisfactory-calculator.com".to_string()]
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "OPTIONS".to_string()]
 }
 
 impl Config {
-    fn load() -> Result<Self> {
-        // Try to load development config first
-        if let Ok(config) = Self::load_from_file("config.dev.toml") {
-            println!("Using development configuration from config.dev.toml");
-            return Ok(config);
+    // Returns the validated config along with where it was loaded from. A `--config` path is
+    // loaded as is, otherwise the files in CONFIG_FILES are tried
+    fn load(config_path: Option<&str>) -> Result<(Self, String)> {
+        let (config, source) = Self::load_unchecked(config_path)?;
+        config.validate()?;
+        Ok((config, source))
+    }
+
+    // Like `load` without validating, for --check-config to report every problem on its own
+    fn load_unchecked(config_path: Option<&str>) -> Result<(Self, String)> {
+        let overrides = env_overrides();
+
+        let (mut table, source) = match config_path {
+            Some(path) => (Self::load_table(path)?, path),
+            None => {
+                // Try to load development config first, then fall back to default config
+                let [dev_config, config] = CONFIG_FILES;
+                match Self::load_table(dev_config) {
+                    Ok(table) => (table, dev_config),
+                    Err(_) => match Self::load_table(config) {
+                        Ok(table) => (table, config),
+                        // Containers may configure everything through the environment
+                        Err(_) if !overrides.is_empty() && !Path::new(config).exists() => {
+                            (toml::Table::new(), "environment")
+                        }
+                        Err(e) => {
+                            return Err(
+                                e.context("Failed to load either config.dev.toml or config.toml")
+                            )
+                        }
+                    },
+                }
+            }
+        };
+
+        let mut source = source.to_string();
+        if !overrides.is_empty() {
+            let names: Vec<_> = overrides.iter().map(|(name, _, _)| name.as_str()).collect();
+            source = format!("{} with overrides from {}", source, names.join(", "));
+        }
+
+        // Environment variables take precedence over the file
+        for (_, key, value) in overrides {
+            table.insert(key, value);
+        }
+
+        let mut config: Config = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("Failed to parse configuration from {}", source))?;
+        config.resolve_auto_save_dirs()?;
+        Ok((config, source))
+    }
+
+    // Replaces "auto" in save_dir or save_dirs with the game's own save directories
+    fn resolve_auto_save_dirs(&mut self) -> Result<()> {
+        if !self.save_dirs().iter().any(|dir| dir == AUTO_SAVE_DIR) {
+            return Ok(());
+        }
+        let game_dirs = game_saves::save_dirs(&self.file_extensions)?;
+
+        let mut save_dirs = Vec::new();
+        for dir in self
+            .save_dir
+            .take()
+            .into_iter()
+            .chain(self.save_dirs.drain(..))
+        {
+            if dir == AUTO_SAVE_DIR {
+                save_dirs.extend(game_dirs.iter().cloned());
+            } else {
+                save_dirs.push(dir);
+            }
+        }
+        // The game's directories may also be listed explicitly
+        let mut seen = HashSet::new();
+        save_dirs.retain(|dir| seen.insert(dir.clone()));
+        self.save_dirs = save_dirs;
+        Ok(())
+    }
+
+    // All configured save directories, whether given as `save_dir` or `save_dirs`
+    fn save_dirs(&self) -> Vec<String> {
+        self.save_dir
+            .iter()
+            .chain(self.save_dirs.iter())
+            .cloned()
+            .collect()
+    }
+
+    // Address and port to bind to, with IPv6 addresses in brackets like `[::]:7778`
+    fn bind_address(&self) -> String {
+        match self.address.parse::<IpAddr>() {
+            Ok(address) => SocketAddr::new(address, self.port).to_string(),
+            Err(_) => format!("{}:{}", self.address, self.port),
+        }
+    }
+
+    // Fails with every problem found by `check`, for callers that only need to know whether
+    // the config can be used
+    fn validate(&self) -> Result<()> {
+        let problems = self.check();
+        if !problems.is_empty() {
+            bail!("Invalid configuration: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+
+    // Everything that would prevent the server from starting, empty when the config is fine
+    fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let save_dirs = self.save_dirs();
+        if save_dirs.is_empty() {
+            problems.push("No save directory configured, set save_dir or save_dirs".to_string());
+        }
+        for save_dir in &save_dirs {
+            if save_dir.is_empty() {
+                problems.push(
+                    "Empty save directory, set save_dir to a path like \"saves\" or \"auto\""
+                        .to_string(),
+                );
+            } else if !fs::metadata(save_dir).is_ok_and(|metadata| metadata.is_dir()) {
+                problems.push(format!("Save directory doesn't exist: {:?}", save_dir));
+            }
+        }
+
+        // The viewer fetches saves from another site, so it needs a full URL
+        if self.base_url.is_empty() {
+            problems.push(
+                "No base_url configured, set it to the URL of the server like \"https://sf.example.com\""
+                    .to_string(),
+            );
+        } else {
+            let valid = Absolute::parse(&self.base_url)
+                .is_ok_and(|uri| matches!(uri.scheme(), "http" | "https"));
+            if !valid {
+                problems.push(format!(
+                    "Invalid base_url {:?}, expected a URL with a scheme like \"https://sf.example.com\"",
+                    self.base_url
+                ));
+            }
+        }
+
+        if let Some(blueprint_dir) = &self.blueprint_dir {
+            if !fs::metadata(blueprint_dir).is_ok_and(|metadata| metadata.is_dir()) {
+                problems.push(format!(
+                    "Blueprint directory doesn't exist: {:?}",
+                    blueprint_dir
+                ));
+            }
+        }
+        if let Some(static_dir) = &self.static_dir {
+            if !fs::metadata(static_dir).is_ok_and(|metadata| metadata.is_dir()) {
+                problems.push(format!("Static directory doesn't exist: {:?}", static_dir));
+            }
+        }
+
+        if self.address.parse::<IpAddr>().is_err() {
+            problems.push(format!(
+                "Invalid bind address: {:?}, expected an IPv4 or IPv6 address like \"0.0.0.0\" or \"::\"",
+                self.address
+            ));
+        }
+        if self.port == 0 {
+            problems.push("Port must be between 1 and 65535".to_string());
+        }
+        if self.max_concurrent_downloads == Some(0) {
+            problems.push("max_concurrent_downloads must be at least 1".to_string());
+        }
+        if self.max_download_bytes_per_sec == Some(0) {
+            problems.push("max_download_bytes_per_sec must be at least 1".to_string());
+        }
+        for (alias, name) in &self.aliases {
+            if validate_name(alias).is_err() || validate_name(name).is_err() {
+                problems.push(format!(
+                    "Invalid alias {:?} = {:?}, only letters, digits, spaces, '_', '-' and '.' are allowed",
+                    alias, name
+                ));
+            }
+        }
+        for (name, profile) in &self.profiles {
+            // Profile names are route prefixes, which can't contain spaces
+            if validate_name(name).is_err()
+                || name.contains(' ')
+                || RESERVED_PROFILE_NAMES.contains(&name.as_str())
+            {
+                problems.push(format!(
+                    "Invalid profile name {:?}, only letters, digits, '_', '-' and '.' are allowed and it may not be one of {}",
+                    name,
+                    RESERVED_PROFILE_NAMES.join(", ")
+                ));
+            }
+            let save_dirs = profile.save_dirs();
+            if save_dirs.is_empty() {
+                problems.push(format!(
+                    "No save directory configured for profile {}, set save_dir or save_dirs",
+                    name
+                ));
+            }
+            for save_dir in &save_dirs {
+                if !fs::metadata(save_dir).is_ok_and(|metadata| metadata.is_dir()) {
+                    problems.push(format!(
+                        "Save directory of profile {} doesn't exist: {:?}",
+                        name, save_dir
+                    ));
+                }
+            }
+        }
+        if self.index_refresh_secs == Some(0) {
+            problems.push("index_refresh_secs must be at least 1".to_string());
+        }
+        for (extension, mime) in &self.mime_overrides {
+            if ContentType::parse_flexible(mime).is_none() {
+                problems.push(format!(
+                    "Invalid MIME type {:?} for extension {:?}, expected e.g. \"application/json\"",
+                    mime, extension
+                ));
+            }
+        }
+        if let Some(target) = self
+            .root_redirect
+            .as_deref()
+            .filter(|target| !target.is_empty())
+        {
+            if Reference::parse(target).is_err() {
+                problems.push(format!(
+                    "Invalid root_redirect {:?}, expected e.g. \"/map\" or \"https://example.com/map\"",
+                    target
+                ));
+            }
+        }
+        if self.serve_stable_only && self.min_age_secs == 0 {
+            problems.push("min_age_secs must be at least 1 with serve_stable_only".to_string());
+        }
+
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if let Err(e) = fs::File::open(path) {
+                        problems.push(format!("Failed to read TLS file {}: {}", path, e));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => problems.push("Both tls_cert and tls_key are required to enable TLS".to_string()),
+        }
+
+        for origin in &self.allowed_origins {
+            let valid =
+                Absolute::parse(origin).is_ok_and(|uri| matches!(uri.scheme(), "http" | "https"));
+            if !valid {
+                problems.push(format!(
+                    "Invalid allowed origin {:?}, expected e.g. \"https://example.com\"",
+                    origin
+                ));
+            }
+        }
+        if self.allowed_methods.is_empty() {
+            problems.push("allowed_methods must contain at least one method".to_string());
+        }
+        for method in &self.allowed_methods {
+            if method.parse::<Method>().is_err() {
+                problems.push(format!(
+                    "Invalid allowed method {:?}, expected e.g. \"GET\"",
+                    method
+                ));
+            }
         }
 
-        // Fall back to default config
-        Self::load_from_file("config.toml")
-            .context("Failed to load either config.dev.toml or config.toml")
+        problems
     }
 
-    fn load_from_file(path: &str) -> Result<Self> {
+    fn load_table(path: &str) -> Result<toml::Table> {
         let contents =
             fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
 
@@ -46,184 +543,2472 @@ impl Config {
     }
 }
 
+const ENV_PREFIX: &str = "SERVE_MAP_";
+
+// Config values from `SERVE_MAP_<KEY>` environment variables, e.g. `SERVE_MAP_SAVE_DIR`.
+fn env_overrides() -> Vec<(String, String, toml::Value)> {
+    let mut overrides: Vec<_> = std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let value = override_value(&key, value);
+            Some((name, key, value))
+        })
+        .collect();
+    overrides.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+    overrides
+}
+
+// Values are read as TOML, e.g. `8080` or `["a", "b"]`, and as plain strings otherwise.
+// Options that take a string keep values like an access token of only digits as they are.
+fn override_value(key: &str, value: String) -> toml::Value {
+    let parsed = toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"));
+    let string = toml::Value::String(value);
+    match parsed {
+        Some(parsed) if parsed.is_str() || accepts(key, &parsed) || !accepts(key, &string) => {
+            parsed
+        }
+        _ => string,
+    }
+}
+
+// Whether the option takes the value, checked by parsing a config with only this option.
+// That leaves required options missing, which is the only error for a value of the right type.
+fn accepts(key: &str, value: &toml::Value) -> bool {
+    let table = toml::Table::from_iter([(key.to_string(), value.clone())]);
+    match toml::Value::Table(table).try_into::<Config>() {
+        Ok(_) => true,
+        Err(e) => e.message().starts_with("missing field"),
+    }
+}
+
 // Custom error type for more informative responses
-#[derive(Debug, Responder)]
+#[derive(Debug)]
 enum MapError {
-    #[response(status = 404)]
     NotFound(String),
-    #[response(status = 400)]
     BadRequest(String),
+    Unauthorized(String),
+    InvalidSave(String),
+    NotImplemented(String),
+    Internal(String),
+    Unavailable(String),
+    TooLarge(String),
+    Busy(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'static str,
+    message: &'a str,
+}
+
+impl MapError {
+    // Status and machine-readable error code of the response
+    fn status(&self) -> (Status, &'static str) {
+        match self {
+            MapError::NotFound(_) => (Status::NotFound, "not_found"),
+            MapError::BadRequest(_) => (Status::BadRequest, "bad_request"),
+            MapError::Unauthorized(_) => (Status::Unauthorized, "unauthorized"),
+            MapError::InvalidSave(_) => (Status::UnprocessableEntity, "invalid_save"),
+            MapError::NotImplemented(_) => (Status::NotImplemented, "not_implemented"),
+            MapError::Internal(_) => (Status::InternalServerError, "internal"),
+            MapError::Unavailable(_) => (Status::ServiceUnavailable, "unavailable"),
+            MapError::TooLarge(_) => (Status::PayloadTooLarge, "too_large"),
+            MapError::Busy(_) => (Status::ServiceUnavailable, "busy"),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            MapError::NotFound(message)
+            | MapError::BadRequest(message)
+            | MapError::Unauthorized(message)
+            | MapError::InvalidSave(message)
+            | MapError::NotImplemented(message)
+            | MapError::Internal(message)
+            | MapError::Unavailable(message)
+            | MapError::TooLarge(message)
+            | MapError::Busy(message) => message,
+        }
+    }
+}
+
+// Errors are sent as JSON, e.g. `{"error":"not_found","message":"..."}`, or as an HTML
+// page when the client prefers it, like browsers following a link from the index
+impl<'r> Responder<'r, 'static> for MapError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (status, error) = self.status();
+
+        let prefers_html = request
+            .accept()
+            .is_some_and(|accept| accept.preferred().is_html());
+        let mut response = if prefers_html {
+            // Link back to the index of the profile the route is mounted for, and keep the
+            // token so the link works when one is required
+            let base = request.route().map_or("/", |route| route.uri.base());
+            let mut index_url = format!("{}/map", base.trim_end_matches('/'));
+            if let Some(token) = request.query_value::<&str>("token").and_then(Result::ok) {
+                index_url.push_str(&format!("?token={}", url_encode(token)));
+            }
+            let html =
+                index_template::render_error(&status.to_string(), self.message(), &index_url);
+            Response::build_from(RawHtml(html).respond_to(request)?)
+                .status(status)
+                .finalize()
+        } else {
+            let body = ErrorBody {
+                error,
+                message: self.message(),
+            };
+            Response::build_from(Json(body).respond_to(request)?)
+                .status(status)
+                .finalize()
+        };
+
+        if let MapError::Busy(_) = self {
+            response.set_header(Header::new(
+                "Retry-After",
+                download_limit::RETRY_AFTER_SECS.to_string(),
+            ));
+        }
+        Ok(response)
+    }
+}
+
+// save_dir value that serves the saves from where the game stores them
+const AUTO_SAVE_DIR: &str = "auto";
+
+// Config files tried in order, the first one that exists is used, unless --config is given.
+// --no-dev-config only loads the second one.
+const CONFIG_FILES: [&str; 2] = ["config.dev.toml", "config.toml"];
+
+// Options that `hot_reload` can change while the server runs
+#[derive(PartialEq)]
+struct LiveConfig {
+    allowed_origins: Vec<String>,
+    hidden_names: Vec<String>,
+    map_viewer_url_template: String,
+}
+
+impl LiveConfig {
+    fn from_config(config: &Config) -> Self {
+        LiveConfig {
+            allowed_origins: config.allowed_origins.clone(),
+            hidden_names: config.hidden_names.clone(),
+            map_viewer_url_template: config.map_viewer_url_template.clone(),
+        }
+    }
 }
 
 // State structure to hold our configuration
 struct ServerConfig {
-    save_dir: String,
+    save_dirs: Vec<String>,
     base_url: String,
+    compress_saves: bool,
+    cache: Arc<SaveCache>,
+    access_token: Option<String>,
+    file_extensions: Vec<String>,
+    // Keyed by lowercase extension without the leading `.`
+    mime_overrides: HashMap<String, ContentType>,
+    index_template: Option<String>,
+    index_title: String,
+    index_sort: IndexSort,
+    rate_limiter: Option<RateLimiter>,
+    // Peers whose X-Forwarded-For is used as the client IP
+    trusted_proxies: Vec<IpAddr>,
+    download_limiter: Option<DownloadLimiter>,
+    download_throttle: Option<DownloadThrottle>,
+    parse_saves: bool,
+    name_suffixes: Vec<String>,
+    autosave_suffix: String,
+    live: Arc<RwLock<LiveConfig>>,
+    // Shared by all profiles, which record their downloads as `<profile>/<name>`
+    metrics: Arc<Metrics>,
+    case_insensitive_names: bool,
+    blueprint_dir: Option<String>,
+    max_file_size_bytes: Option<u64>,
+    hashes: Arc<HashCache>,
+    recursive: bool,
+    autosave_warn_threshold: Option<usize>,
+    index_refresh_secs: Option<u64>,
+    cache_control: Option<String>,
+    // Where `/` redirects to, `None` leaves `/` unrouted
+    root_redirect: Option<String>,
+    follow_symlinks: bool,
+    index_message: Option<String>,
+    // Minimum age of served saves, `None` serves saves that are still being written
+    min_save_age: Option<Duration>,
+    aliases: HashMap<String, String>,
+    // When the server was built right before launch, for the uptime
+    started_at: Instant,
+    // Name of the profile, `None` for the top-level saves
+    profile: Option<String>,
 }
 
-#[get("/map/<name>")]
-async fn serve_map(name: &str, config: &State<ServerConfig>) -> Result<NamedFile, MapError> {
-    // Basic input validation
-    if name.contains(['/', '\\', '.']) {
-        return Err(MapError::BadRequest("Invalid characters in name".into()));
+impl ServerConfig {
+    // The config of a profile, which shares everything but the saves with the top-level one.
+    // Requests are rate limited by the top-level config, so the profile needs no limiter.
+    fn for_profile(&self, name: &str, profile: &ProfileConfig) -> ServerConfig {
+        ServerConfig {
+            save_dirs: profile.save_dirs(),
+            // Links on the listings and in latest.json point to the profile's routes
+            base_url: format!("{}/{}", self.base_url, name),
+            compress_saves: self.compress_saves,
+            // Lookups are cached by name, which the profile's saves may share with others
+            cache: Arc::new(SaveCache::new(self.cache.ttl(), self.recursive)),
+            access_token: self.access_token.clone(),
+            file_extensions: self.file_extensions.clone(),
+            mime_overrides: self.mime_overrides.clone(),
+            index_template: self.index_template.clone(),
+            index_title: profile
+                .index_title
+                .clone()
+                .unwrap_or_else(|| self.index_title.clone()),
+            index_sort: self.index_sort,
+            rate_limiter: None,
+            trusted_proxies: self.trusted_proxies.clone(),
+            download_limiter: self.download_limiter.clone(),
+            download_throttle: self.download_throttle.clone(),
+            parse_saves: self.parse_saves,
+            name_suffixes: self.name_suffixes.clone(),
+            autosave_suffix: self.autosave_suffix.clone(),
+            live: self.live.clone(),
+            metrics: self.metrics.clone(),
+            case_insensitive_names: self.case_insensitive_names,
+            blueprint_dir: self.blueprint_dir.clone(),
+            max_file_size_bytes: self.max_file_size_bytes,
+            hashes: self.hashes.clone(),
+            recursive: self.recursive,
+            autosave_warn_threshold: self.autosave_warn_threshold,
+            index_refresh_secs: self.index_refresh_secs,
+            cache_control: self.cache_control.clone(),
+            root_redirect: self.root_redirect.clone(),
+            follow_symlinks: self.follow_symlinks,
+            index_message: self.index_message.clone(),
+            min_save_age: self.min_save_age,
+            aliases: self.aliases.clone(),
+            started_at: self.started_at,
+            profile: Some(name.to_string()),
+        }
     }
 
-    let pattern = format!("{}/{}*.sav", config.save_dir, name);
+    // Name of a save in the metrics, prefixed with the profile so names don't mix
+    fn metrics_name(&self, name: &str) -> String {
+        match &self.profile {
+            Some(profile) => format!("{}/{}", profile, name),
+            None => name.to_string(),
+        }
+    }
 
-    let latest_save = glob(&pattern)
-        .map_err(|e| MapError::BadRequest(format!("Invalid pattern: {}", e)))?
-        .filter_map(Result::ok)
-        .filter_map(|path| {
-            path.metadata()
-                .ok()
-                .map(|metadata| (path, metadata.modified().unwrap()))
-        })
-        .max_by_key(|&(_, modified_time)| modified_time)
-        .map(|(path, _)| path);
+    // Save files in any of the save directories whose name starts with the prefix
+    fn save_files(&self, prefix: &str, case_sensitive: bool) -> Vec<PathBuf> {
+        matching_files(
+            &self.save_dirs,
+            &self.file_extensions,
+            prefix,
+            self.recursive,
+            case_sensitive,
+            self.follow_symlinks,
+        )
+    }
+
+    // Files of the save with exactly this name, e.g. `Factory` matches `Factory_autosave_1.sav`
+    // but not `FactoryTwo.sav`. A full file stem like `Factory_autosave_1` matches that file.
+    fn named_save_files(&self, name: &str, case_sensitive: bool) -> Vec<PathBuf> {
+        let equals = |a: &str, b: &str| {
+            if case_sensitive {
+                a == b
+            } else {
+                a.eq_ignore_ascii_case(b)
+            }
+        };
+
+        // The prefix match narrows the directory listing down, the save name decides
+        self.save_files(name, case_sensitive)
+            .into_iter()
+            .filter(|path| {
+                path.file_name().is_some_and(|file_name| {
+                    let file_name = file_name.to_string_lossy();
+                    self.split_extension(&file_name).is_some_and(|(stem, _)| {
+                        equals(stem, name) || equals(self.save_name(stem), name)
+                    })
+                })
+            })
+            .collect()
+    }
+
+    // The save name an alias points to, or the name itself when it isn't an alias
+    fn resolve_alias<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map_or(name, String::as_str)
+    }
 
-    match latest_save {
-        Some(path) => {
-            println!("Serving file: {}", path.display());
-            NamedFile::open(&path)
-                .await
-                .map_err(|e| MapError::NotFound(format!("Failed to open file: {}", e)))
+    // Splits a file name into its stem and the configured extension it ends with. The
+    // extension may differ in case, files ignoring case were already matched by the scan.
+    fn split_extension<'a>(&self, file_name: &'a str) -> Option<(&'a str, &'a str)> {
+        self.file_extensions
+            .iter()
+            .filter_map(|extension| {
+                let split = file_name.len().checked_sub(extension.len())?;
+                let suffix = file_name.get(split..)?;
+                if !suffix.eq_ignore_ascii_case(extension) {
+                    return None;
+                }
+                let stem = file_name[..split].strip_suffix('.')?;
+                Some((stem, suffix))
+            })
+            // Prefer the longest extension, e.g. `sav.bak` over `bak`
+            .max_by_key(|(_, extension)| extension.len())
+    }
+
+    // The configured MIME type of an extension like `sav.bak`, otherwise a guess from its
+    // last part, falling back to a plain download
+    fn content_type(&self, extension: &str) -> ContentType {
+        let extension = extension.to_ascii_lowercase();
+        self.mime_overrides
+            .get(&extension)
+            .cloned()
+            .or_else(|| ContentType::from_extension(extension.rsplit('.').next()?))
+            .unwrap_or(ContentType::Binary)
+    }
+
+    // Link to the map viewer for a save, with the query parameters of its save URL, e.g.
+    // `manual=true` to link to its latest manual save
+    fn viewer_url(&self, name: &str, params: &[(&str, String)]) -> String {
+        // The save URL is a parameter of the viewer URL, so a second parameter needs an encoded `&`
+        let mut query = self.token_query();
+        for (key, value) in params {
+            query.push_str(if query.is_empty() { "?" } else { "%26" });
+            query.push_str(&format!("{}={}", key, value));
         }
-        None => {
-            let msg = format!("No matching files found for pattern: {}", pattern);
-            println!("{}", msg);
-            Err(MapError::NotFound(msg))
+
+        self.live
+            .read()
+            .unwrap()
+            .map_viewer_url_template
+            .replace("{base_url}", &self.base_url)
+            .replace("{name}", &format!("{}{}", url_encode(name), query))
+    }
+
+    // The calculator can't send headers, so links need to carry the token themselves
+    fn token_query(&self) -> String {
+        self.access_token
+            .as_ref()
+            .map(|token| format!("?token={}", url_encode(token)))
+            .unwrap_or_default()
+    }
+
+    // Whether the file is an autosave, judged by its file name
+    fn save_kind(&self, path: &Path) -> SaveKind {
+        if self.autosave_slot(path).is_some() {
+            SaveKind::Autosave
+        } else {
+            SaveKind::Manual
         }
     }
+
+    // The number of an autosave, e.g. 2 for `My_Factory_autosave_2.sav`, `None` for manual saves
+    fn autosave_slot(&self, path: &Path) -> Option<u64> {
+        let file_name = path.file_name()?.to_string_lossy();
+        let (stem, _) = self.split_extension(&file_name)?;
+        let name = strip_name_suffix(stem, &self.autosave_suffix)?;
+
+        let digits: String = stem[name.len()..]
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        digits.parse().ok()
+    }
+
+    // The save name of a file stem with the configured suffix stripped, e.g.
+    // `My_Factory` for `My_Factory_autosave_1`. The longest matching suffix wins.
+    fn save_name<'a>(&self, stem: &'a str) -> &'a str {
+        self.name_suffixes
+            .iter()
+            .filter_map(|suffix| strip_name_suffix(stem, suffix))
+            .filter(|name| !name.is_empty())
+            .min_by_key(|name| name.len())
+            .unwrap_or(stem)
+    }
 }
 
-#[get("/map")]
-fn map_index(config: &State<ServerConfig>) -> Result<RawHtml<String>, MapError> {
-    let pattern = format!("{}/*.sav", config.save_dir);
+// Suffix the game appends to autosaves, manual saves are named freely
+const AUTOSAVE_SUFFIX: &str = "_autosave_{n}";
 
-    // Collect unique save names
-    let mut save_names: HashSet<String> = HashSet::new();
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SaveKind {
+    Manual,
+    Autosave,
+}
 
-    for entry in
-        glob(&pattern).map_err(|e| MapError::BadRequest(format!("Invalid pattern: {}", e)))?
-    {
-        if let Ok(path) = entry {
-            if let Some(file_name) = path.file_name() {
-                if let Some(name) = file_name.to_string_lossy().split('_').next() {
-                    save_names.insert(name.to_string());
+// Order of the saves on the /map page
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum IndexSort {
+    // Alphabetically, ignoring case
+    #[default]
+    Name,
+    // Newest latest save first
+    Recent,
+    // Largest total size first
+    Size,
+}
+
+// Strips a suffix where `{n}` stands for one or more digits
+fn strip_name_suffix<'a>(stem: &'a str, suffix: &str) -> Option<&'a str> {
+    let mut rest = stem;
+    let mut parts = suffix.rsplit("{n}").peekable();
+
+    while let Some(literal) = parts.next() {
+        rest = rest.strip_suffix(literal)?;
+        if parts.peek().is_some() {
+            let digits = rest.len() - rest.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            rest = &rest[..rest.len() - digits];
+        }
+    }
+
+    Some(rest)
+}
+
+// Files named `<prefix>*.<extension>` in any of the directories, including their
+// subdirectories when recursive. Unreadable directories are skipped, `check_save_dirs`
+// reports them.
+fn matching_files(
+    dirs: &[String],
+    extensions: &[String],
+    prefix: &str,
+    recursive: bool,
+    case_sensitive: bool,
+    follow_symlinks: bool,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = dirs.iter().map(PathBuf::from).collect();
+    // Directories listed twice or reached again through a symlink are only read once
+    let mut visited = HashSet::new();
+
+    while let Some(dir) = pending.pop() {
+        if !visited.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if path.is_dir() {
+                if recursive {
+                    pending.push(path);
                 }
+                continue;
+            }
+
+            let matches = path.file_name().is_some_and(|file_name| {
+                matches_file_name(
+                    &file_name.to_string_lossy(),
+                    prefix,
+                    extensions,
+                    case_sensitive,
+                )
+            });
+            if matches && is_usable(&path, follow_symlinks) {
+                files.push(path);
             }
         }
     }
 
-    let mut html = String::from(
-        r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>Satisfactory Saves</title>
-    <style>
-        body { font-family: Arial, sans-serif; max-width: 800px; margin: 2em auto; padding: 0 1em; }
-        h1 { color: #333; }
-        .save-list { list-style: none; padding: 0; }
-        .save-list li { margin: 1em 0; }
-        .save-list a {
-            display: inline-block;
-            padding: 0.5em 1em;
-            background: #4CAF50;
-            color: white;
-            text-decoration: none;
-            border-radius: 4px;
-            transition: background 0.2s;
-        }
-        .save-list a:hover { background: #45a049; }
-    </style>
-</head>
-<body>
-    <h1>Available Satisfactory Saves</h1>
-    <ul class="save-list">
-"#,
-    );
+    files
+}
 
-    let saves: Vec<_> = save_names.into_iter().collect();
-    for save in saves {
-        html.push_str(&format!(
-            r#"        <li><a href="https://satisfactory-calculator.com/en/interactive-map?url={}/map/{}">{}</a></li>
-"#,
-            config.base_url, save, save
-        ));
+// Whether the file name starts with the prefix and ends with `.<extension>` for any of
+// the extensions. Ignoring case only applies to ASCII letters.
+fn matches_file_name(
+    file_name: &str,
+    prefix: &str,
+    extensions: &[String],
+    case_sensitive: bool,
+) -> bool {
+    let fold = |text: &str| {
+        if case_sensitive {
+            text.to_string()
+        } else {
+            text.to_ascii_lowercase()
+        }
+    };
+    let file_name = fold(file_name);
+    let prefix = fold(prefix);
+
+    extensions.iter().any(|extension| {
+        let suffix = format!(".{}", fold(extension));
+        file_name.len() >= prefix.len() + suffix.len()
+            && file_name.starts_with(&prefix)
+            && file_name.ends_with(&suffix)
+    })
+}
+
+// Broken symlinks are always skipped, working ones only when follow_symlinks is off
+fn is_usable(path: &Path, follow_symlinks: bool) -> bool {
+    if !follow_symlinks
+        && path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return false;
     }
+    path.metadata().is_ok()
+}
 
-    html.push_str(
-        r#"    </ul>
-</body>
-</html>"#,
-    );
+// Sorts the files by their modified time, newest first
+fn sorted_saves(paths: Vec<PathBuf>) -> Vec<(PathBuf, SystemTime)> {
+    let mut saves: Vec<_> = paths
+        .into_iter()
+        // Files deleted in the meantime or without a modified time can't be ordered
+        .filter_map(|path| {
+            let modified_time = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((path, modified_time))
+        })
+        .collect();
 
-    Ok(RawHtml(html))
+    // Newest first, equal modified times fall back to the greatest file name so the
+    // same file is picked on every request
+    saves.sort_by(|(path_a, time_a), (path_b, time_b)| {
+        time_b
+            .cmp(time_a)
+            .then_with(|| path_b.file_name().cmp(&path_a.file_name()))
+    });
+    saves
 }
 
-#[options("/<_..>")]
-fn all_options() {
-    /* Intentionally left empty */
+// Fails when a save directory went missing or can't be read, e.g. after a network
+// mount dropped, so it is not mistaken for a save that doesn't exist
+fn check_save_dirs(config: &ServerConfig) -> Result<(), MapError> {
+    for save_dir in &config.save_dirs {
+        if let Err(e) = fs::read_dir(save_dir) {
+            let msg = format!("Save directory {} is unavailable: {}", save_dir, e);
+            error!("{}", msg);
+            return Err(MapError::Unavailable(msg));
+        }
+    }
+    Ok(())
 }
 
-pub struct CORS;
+// Percent-encodes a save name or token for a link, e.g. the space in `My Factory`
+fn url_encode(text: &str) -> String {
+    RawStr::new(text).percent_encode().to_string()
+}
 
-#[rocket::async_trait]
-impl Fairing for CORS {
-    fn info(&self) -> Info {
-        Info {
-            name: "Add CORS headers to responses",
-            kind: Kind::Response,
+// Save names may only contain ASCII letters, digits, spaces, `_`, `-` and `.`, and may not
+// start with a `.`. The game allows spaces in session names.
+fn validate_name(name: &str) -> Result<(), MapError> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.'));
+
+    if name.is_empty() || name.starts_with('.') || !valid_chars {
+        return Err(MapError::BadRequest(format!(
+            "Invalid save name {:?}, only letters, digits, spaces, '_', '-' and '.' are allowed and it may not start with '.'",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+// Finds all saves for the given save name, newest first
+fn find_saves(name: &str, config: &ServerConfig) -> Result<Vec<(PathBuf, SystemTime)>, MapError> {
+    validate_name(name)?;
+    let name = config.resolve_alias(name);
+
+    let saves = match config.cache.get(name, &config.save_dirs) {
+        Some(saves) => saves,
+        None => {
+            let mut saves = sorted_saves(config.named_save_files(name, true));
+            // Only fall back to ignoring case when nothing matches exactly, to avoid ambiguity
+            if saves.is_empty() && config.case_insensitive_names {
+                saves = sorted_saves(config.named_save_files(name, false));
+            }
+            config.cache.insert(name, &config.save_dirs, saves.clone());
+            saves
         }
+    };
+
+    if saves.is_empty() {
+        check_save_dirs(config)?;
+        let msg = format!(
+            "No matching files found for {} in {}",
+            name,
+            config.save_dirs.join(", ")
+        );
+        warn!("{}", msg);
+        return Err(MapError::NotFound(msg));
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new(
-            "Access-Control-Allow-Origin",
-            "https://satisfactory-calculator.com",
-        ));
-        response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
-        ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+    Ok(saves)
+}
+
+#[get("/map/<name>?<index>&<before>&<manual>&<download>")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_map(
+    name: &str,
+    index: Option<usize>,
+    before: Option<u64>,
+    manual: Option<bool>,
+    download: Option<bool>,
+    accept: Option<&Accept>,
+    conditional: Conditional,
+    accept_encoding: AcceptEncoding,
+    _auth: Authorized,
+    _rate_limited: RateLimited,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<Either<Redirect, SaveResponse>, MapError> {
+    let manual = manual.unwrap_or(false);
+
+    // People opening a link in the browser want the map, not a binary download. The viewer
+    // itself fetches the save without preferring HTML, so it still gets the file.
+    let prefers_html = accept.is_some_and(|accept| accept.preferred().is_html());
+    if prefers_html && !download.unwrap_or(false) {
+        find_saves(name, config)?;
+        // The viewer should show the save the link picks
+        let params = [
+            ("index", index.map(|index| index.to_string())),
+            ("before", before.map(|before| before.to_string())),
+            ("manual", manual.then(|| "true".to_string())),
+        ];
+        let params: Vec<_> = params
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+        let url = config.viewer_url(name, &params);
+        span.0
+            .in_scope(|| info!(save_name = name, "Redirecting browser to {}", url));
+        return Ok(Either::Left(Redirect::found(url)));
     }
+
+    let index = index.unwrap_or(0);
+    serve_save(
+        name,
+        index,
+        before,
+        manual,
+        &conditional,
+        &accept_encoding,
+        &span,
+        config,
+    )
+    .await
+    .map(Either::Right)
 }
 
-#[rocket::main]
-async fn main() -> rocket_anyhow::Result {
-    // Load configuration
-    let config = Config::load()?;
+// The save before the latest one, e.g. after a bad autosave
+#[get("/map/<name>/previous")]
+async fn serve_previous(
+    name: &str,
+    conditional: Conditional,
+    accept_encoding: AcceptEncoding,
+    _auth: Authorized,
+    _rate_limited: RateLimited,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<SaveResponse, MapError> {
+    serve_save(
+        name,
+        1,
+        None,
+        false,
+        &conditional,
+        &accept_encoding,
+        &span,
+        config,
+    )
+    .await
+}
 
-    // Validate save directory
-    let path = Path::new(&config.save_dir);
-    if !std::fs::metadata(&path)?.is_dir() {
-        eprintln!("Save directory doesn't exist: {:?}", path);
-        std::process::exit(1);
+// The newest file of any save, e.g. to bookmark the world currently being played.
+// Static segments rank before `/map/<name>`, so a save named "latest" is shadowed
+#[get("/map/latest")]
+async fn serve_latest(
+    conditional: Conditional,
+    accept_encoding: AcceptEncoding,
+    _auth: Authorized,
+    _rate_limited: RateLimited,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<SaveResponse, MapError> {
+    check_save_dirs(config)?;
+    let mut saves = sorted_saves(config.save_files("", true));
+    if let Some(min_age) = config.min_save_age {
+        saves = stable_saves(saves, min_age, &span).await?;
+    }
+
+    // Served as picked, looking it up again by its name would apply aliases to it. Like on
+    // the listings, saves with names that can't be requested are left out.
+    let latest = saves.into_iter().find_map(|(path, modified_time)| {
+        let file_name = path.file_name()?.to_string_lossy();
+        let (stem, _) = config.split_extension(&file_name)?;
+        let name = config.save_name(stem).to_string();
+        validate_name(&name).ok()?;
+        Some((path, modified_time, name))
+    });
+    let Some((path, modified_time, name)) = latest else {
+        let msg = "No save files found".to_string();
+        span.0.in_scope(|| warn!("{}", msg));
+        return Err(MapError::NotFound(msg));
     };
 
-    println!("Server starting with configuration:");
-    println!("  Save directory: {}", config.save_dir);
-    println!("  Port: {}", config.port);
-    println!("  Base URL: {}", config.base_url);
-    println!("\nEndpoints available:");
-    println!("  - /map/<name>     : Serves the latest save file");
-    println!("  - /map            : Serves a list of available maps");
+    send_save(
+        &path,
+        modified_time,
+        &name,
+        &conditional,
+        &accept_encoding,
+        &span,
+        config,
+    )
+    .await
+}
 
-    let server_config = ServerConfig {
-        save_dir: config.save_dir,
-        base_url: config.base_url,
+// Leaves out saves modified less than `min_age` ago, which the game may still be writing.
+// When every save is that new, e.g. in a new session, waits once for the newest one to settle.
+async fn stable_saves(
+    saves: Vec<(PathBuf, SystemTime)>,
+    min_age: Duration,
+    span: &RequestSpan,
+) -> Result<Vec<(PathBuf, SystemTime)>, MapError> {
+    // Cached modified times can predate a write in progress, so ask the file system again.
+    // Times in the future count as just modified.
+    let modified = |path: &Path| {
+        path.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let age = |modified: SystemTime| modified.elapsed().unwrap_or_default();
+
+    let Some((newest, _)) = saves.first().cloned() else {
+        return Ok(saves);
     };
+    let total = saves.len();
+    let stable: Vec<_> = saves
+        .into_iter()
+        .filter(|(path, _)| modified(path).is_none_or(|modified| age(modified) >= min_age))
+        .collect();
+    if !stable.is_empty() {
+        if stable.len() < total {
+            span.0.in_scope(|| {
+                info!(
+                    "Skipping {} saves modified less than {}s ago",
+                    total - stable.len(),
+                    min_age.as_secs()
+                )
+            });
+        }
+        return Ok(stable);
+    }
 
-    let figment = rocket::Config::figment().merge(("port", config.port));
+    let remaining = min_age.saturating_sub(modified(&newest).map(age).unwrap_or_default());
+    span.0.in_scope(|| {
+        info!(
+            "Waiting {}ms for {} to be written",
+            remaining.as_millis(),
+            newest.display()
+        )
+    });
+    rocket::tokio::time::sleep(remaining).await;
 
-    rocket::custom(figment)
-        .attach(CORS)
-        //.ignite()
-        .mount("/", routes![serve_map, map_index, all_options])
-        .manage(server_config)
-        .launch()
-        .await?;
+    match modified(&newest) {
+        Some(modified_time) if age(modified_time) >= min_age => Ok(vec![(newest, modified_time)]),
+        _ => {
+            let msg = format!(
+                "{} is still being written, try again in a few seconds",
+                newest.file_name().unwrap_or_default().to_string_lossy()
+            );
+            span.0.in_scope(|| warn!("{}", msg));
+            Err(MapError::Unavailable(msg))
+        }
+    }
+}
+
+// The file that `/map/<name>` serves, which the routes about the latest save describe
+async fn latest_save_file(
+    name: &str,
+    span: &RequestSpan,
+    config: &ServerConfig,
+) -> Result<(PathBuf, SystemTime), MapError> {
+    let mut saves = find_saves(name, config)?;
+    if let Some(min_age) = config.min_save_age {
+        saves = stable_saves(saves, min_age, span).await?;
+    }
+
+    Ok(saves.remove(0))
+}
+
+// Serves the save at the index of the matching saves, newest first
+#[allow(clippy::too_many_arguments)]
+async fn serve_save(
+    name: &str,
+    index: usize,
+    before: Option<u64>,
+    manual_only: bool,
+    conditional: &Conditional,
+    accept_encoding: &AcceptEncoding,
+    span: &RequestSpan,
+    config: &ServerConfig,
+) -> Result<SaveResponse, MapError> {
+    let mut saves = find_saves(name, config)?;
+
+    // Only consider saves older than the given unix timestamp
+    if let Some(before) = before {
+        let cutoff = UNIX_EPOCH + Duration::from_secs(before);
+        saves.retain(|&(_, modified_time)| modified_time < cutoff);
+    }
+    if manual_only {
+        saves.retain(|(path, _)| config.save_kind(path) == SaveKind::Manual);
+    }
+    if let Some(min_age) = config.min_save_age {
+        saves = stable_saves(saves, min_age, span).await?;
+    }
+
+    let count = saves.len();
+
+    let Some((path, modified_time)) = saves.into_iter().nth(index) else {
+        let msg = format!(
+            "Save index {} out of range, {} matching files for {}",
+            index, count, name
+        );
+        span.0.in_scope(|| warn!("{}", msg));
+        return Err(MapError::NotFound(msg));
+    };
+
+    let name = config.resolve_alias(name);
+    send_save(
+        &path,
+        modified_time,
+        name,
+        conditional,
+        accept_encoding,
+        span,
+        config,
+    )
+    .await
+}
+
+// Sends a file of the save with the given name, which must be valid as it is also the
+// download name, unlike the internal file name
+async fn send_save(
+    path: &Path,
+    modified_time: SystemTime,
+    name: &str,
+    conditional: &Conditional,
+    accept_encoding: &AcceptEncoding,
+    span: &RequestSpan,
+    config: &ServerConfig,
+) -> Result<SaveResponse, MapError> {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = config
+        .split_extension(&file_name)
+        .map(|(_, extension)| extension)
+        .unwrap_or("sav");
+    let content_type = config.content_type(extension);
+    let encoding = if config.compress_saves {
+        accept_encoding.preferred()
+    } else {
+        None
+    };
+    // Take a slot before touching the file, it is held until the body was sent
+    let permit = match &config.download_limiter {
+        Some(limiter) => match limiter.try_acquire() {
+            Some(permit) => Some(permit),
+            None => {
+                let msg = "Too many downloads in progress, try again later".to_string();
+                span.0.in_scope(|| warn!("{}", msg));
+                return Err(MapError::Busy(msg));
+            }
+        },
+        None => None,
+    };
+    let download_name = format!("{}.{}", name, extension);
+    // A `.gz` next to the save is sent as is, which is cheaper than compressing on the fly
+    let precompressed = if accept_encoding.accepts_gzip() {
+        precompressed_path(path, modified_time)
+    } else {
+        None
+    };
+    // Size of the save itself, for the size limit and the metrics
+    let (mut save, save_size) = match precompressed {
+        Some(gz_path) => {
+            span.0
+                .in_scope(|| debug!("Serving precompressed {}", gz_path.display()));
+            let mut save = open_save(&gz_path, download_name, Some(Encoding::Gzip)).await?;
+            save.precompressed = true;
+            // Report the save itself, the `.gz` is only how it is sent
+            save.file_name = file_name;
+            let metadata_error =
+                |e| MapError::Internal(format!("Failed to read file metadata: {}", e));
+            let metadata = path.metadata().map_err(metadata_error)?;
+            save.modified = metadata.modified().map_err(metadata_error)?;
+            let save_size = metadata.len();
+            // Its bytes differ from compressing on the fly, so it gets its own etag
+            let etag = save_response::etag(save.modified, save_size);
+            save.etag = format!("{}-gz\"", etag.trim_end_matches('"'));
+            (save, save_size)
+        }
+        None => {
+            let save = open_save(path, download_name, encoding).await?;
+            let save_size = save.size;
+            (save, save_size)
+        }
+    };
+    check_file_size(path, save_size, config)?;
+    let precompressed = save.precompressed;
+    save.content_type = content_type;
+    save.permit = permit;
+    save.throttle = config
+        .download_throttle
+        .as_ref()
+        .map(DownloadThrottle::slot);
+    save.cache_control = config.cache_control.clone();
+
+    let response = SaveResponse::new(save, conditional);
+    // Precompressed saves are always sent whole
+    let bytes = response
+        .body_size()
+        .map(|bytes| if precompressed { save_size } else { bytes });
+    // The most useful line for log collectors, so the details are fields instead of text
+    span.0.in_scope(|| {
+        info!(
+            save_name = name,
+            file = %path.display(),
+            bytes,
+            "Serving file"
+        )
+    });
+    if let Some(bytes) = bytes {
+        config
+            .metrics
+            .record_download(&config.metrics_name(name), bytes);
+    }
+    Ok(response)
+}
+
+// The gzipped copy of a save, unless it is missing or older than the save itself
+fn precompressed_path(path: &Path, modified_time: SystemTime) -> Option<PathBuf> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let gz_path = PathBuf::from(gz_path);
+
+    let gz_modified = gz_path.metadata().ok()?.modified().ok()?;
+    (gz_modified >= modified_time).then_some(gz_path)
+}
+
+// The modified time and size come from the opened file, cached ones may predate a rewrite
+async fn open_save(
+    path: &Path,
+    download_name: String,
+    encoding: Option<Encoding>,
+) -> Result<SaveFile, MapError> {
+    let file = NamedFile::open(path)
+        .await
+        .map_err(|e| MapError::NotFound(format!("Failed to open file: {}", e)))?;
+    let metadata_error = |e| MapError::Internal(format!("Failed to read file metadata: {}", e));
+    let metadata = file.file().metadata().await.map_err(metadata_error)?;
+    let modified = metadata.modified().map_err(metadata_error)?;
+    let size = metadata.len();
+
+    Ok(SaveFile {
+        file,
+        etag: save_response::etag(modified, size),
+        modified,
+        size,
+        download_name,
+        content_type: ContentType::Binary,
+        file_name: path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        encoding,
+        precompressed: false,
+        permit: None,
+        throttle: None,
+        cache_control: None,
+    })
+}
+
+// Guards against serving junk like runaway files
+fn check_file_size(path: &Path, size: u64, config: &ServerConfig) -> Result<(), MapError> {
+    match config.max_file_size_bytes {
+        Some(max) if size > max => {
+            let msg = format!(
+                "{} is {} bytes, larger than the maximum of {} bytes",
+                path.display(),
+                size,
+                max
+            );
+            warn!("{}", msg);
+            Err(MapError::TooLarge(msg))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[get("/map/<name>/info")]
+async fn save_info(
+    name: &str,
+    _auth: Authorized,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<Json<SaveHeader>, MapError> {
+    let (path, _) = latest_save_file(name, &span, config).await?;
+
+    sav_header::parse_header(&path)
+        .map(Json)
+        .map_err(|e| MapError::InvalidSave(format!("{:#}", e)))
+}
+
+#[get("/map/<name>/objects")]
+async fn save_objects(
+    name: &str,
+    _auth: Authorized,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<Json<SaveObjects>, MapError> {
+    if !config.parse_saves {
+        return Err(MapError::NotImplemented(
+            "Parsing saves is disabled, enable parse_saves in the config".into(),
+        ));
+    }
+
+    let (path, _) = latest_save_file(name, &span, config).await?;
+
+    // Walking a large save takes a while, keep it off the async workers
+    rocket::tokio::task::spawn_blocking(move || sav_body::parse_objects(&path))
+        .await
+        .map_err(|e| MapError::Internal(format!("Failed to parse save: {}", e)))?
+        .map(Json)
+        .map_err(|e| MapError::InvalidSave(format!("{:#}", e)))
+}
+
+#[get("/map/<name>/archive.zip")]
+fn save_archive(
+    name: &str,
+    _auth: Authorized,
+    _rate_limited: RateLimited,
+    config: &ServerConfig,
+) -> Result<SaveArchive, MapError> {
+    let paths = find_saves(name, config)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+
+    info!(save_name = name, "Serving archive of all versions");
+    Ok(SaveArchive {
+        name: name.to_string(),
+        paths,
+    })
+}
+
+#[derive(Serialize)]
+struct SaveVersion {
+    filename: String,
+    modified: String,
+    size_bytes: u64,
+    // Short SHA-256 of the contents to spot identical autosaves
+    content_hash: Option<String>,
+    kind: SaveKind,
+}
+
+// Length of the hex encoded content hash prefix in the version list
+const SHORT_HASH_LEN: usize = 16;
+
+#[get("/map/<name>/versions")]
+async fn save_versions(
+    name: &str,
+    _auth: Authorized,
+    config: &ServerConfig,
+) -> Result<Json<Vec<SaveVersion>>, MapError> {
+    let saves: Vec<_> = find_saves(name, config)?
+        .into_iter()
+        .map(|(path, modified_time)| {
+            let kind = config.save_kind(&path);
+            (path, modified_time, kind)
+        })
+        .collect();
+    let hashes = config.hashes.clone();
+
+    // Hashing reads every file that changed since the last request
+    let versions = rocket::tokio::task::spawn_blocking(move || {
+        saves
+            .into_iter()
+            .map(|(path, modified_time, kind)| save_version(&path, modified_time, kind, &hashes))
+            .collect()
+    })
+    .await
+    .map_err(|e| MapError::Internal(format!("Failed to list versions: {}", e)))?;
+
+    Ok(Json(versions))
+}
+
+// Blocks while hashing the file, unless its hash is cached
+fn save_version(
+    path: &Path,
+    modified_time: SystemTime,
+    kind: SaveKind,
+    hashes: &HashCache,
+) -> SaveVersion {
+    SaveVersion {
+        filename: path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        modified: format_rfc3339(modified_time),
+        size_bytes: path.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        content_hash: hashes
+            .sha256(path)
+            .ok()
+            .map(|hash| hash[..SHORT_HASH_LEN].to_string()),
+        kind,
+    }
+}
+
+#[derive(Serialize)]
+struct SaveDiff {
+    name: String,
+    newer: SaveVersion,
+    older: SaveVersion,
+    // Newer minus older, so a growing save has a positive size delta
+    size_delta_bytes: i64,
+    time_delta_secs: i64,
+}
+
+// Answers "did my last session save?" without comparing the whole version list by hand
+#[get("/map/<name>/diff")]
+async fn save_diff(
+    name: &str,
+    _auth: Authorized,
+    config: &ServerConfig,
+) -> Result<Json<SaveDiff>, MapError> {
+    let mut saves = find_saves(name, config)?.into_iter();
+    let name = config.resolve_alias(name).to_string();
+    let (Some((newer_path, newer_time)), Some((older_path, older_time))) =
+        (saves.next(), saves.next())
+    else {
+        let msg = format!("Only one file found for {}, nothing to compare", name);
+        warn!("{}", msg);
+        return Err(MapError::NotFound(msg));
+    };
+    let newer_kind = config.save_kind(&newer_path);
+    let older_kind = config.save_kind(&older_path);
+    let hashes = config.hashes.clone();
+
+    let (newer, older) = rocket::tokio::task::spawn_blocking(move || {
+        (
+            save_version(&newer_path, newer_time, newer_kind, &hashes),
+            save_version(&older_path, older_time, older_kind, &hashes),
+        )
+    })
+    .await
+    .map_err(|e| MapError::Internal(format!("Failed to compare saves: {}", e)))?;
+
+    let time_delta_secs = match newer_time.duration_since(older_time) {
+        Ok(delta) => delta.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    Ok(Json(SaveDiff {
+        name,
+        size_delta_bytes: newer.size_bytes as i64 - older.size_bytes as i64,
+        time_delta_secs,
+        newer,
+        older,
+    }))
+}
+
+#[derive(Serialize)]
+struct Rotation {
+    name: String,
+    // Autosave numbers present, e.g. [0, 1, 2] when the server keeps three autosaves
+    slots: Vec<u64>,
+    slots_in_use: usize,
+    oldest: Option<String>,
+    newest: Option<String>,
+    manual_saves: usize,
+}
+
+// Shows whether the server rotates its autosaves, without logging in to look at the files
+#[get("/map/<name>/rotation")]
+fn save_rotation(
+    name: &str,
+    _auth: Authorized,
+    config: &ServerConfig,
+) -> Result<Json<Rotation>, MapError> {
+    let saves = find_saves(name, config)?;
+
+    // Newest first, like find_saves
+    let autosaves: Vec<_> = saves
+        .iter()
+        .filter_map(|(path, modified_time)| Some((config.autosave_slot(path)?, *modified_time)))
+        .collect();
+    let mut slots: Vec<_> = autosaves.iter().map(|&(slot, _)| slot).collect();
+    slots.sort_unstable();
+    slots.dedup();
+
+    Ok(Json(Rotation {
+        name: config.resolve_alias(name).to_string(),
+        slots_in_use: slots.len(),
+        slots,
+        oldest: autosaves.last().map(|&(_, time)| format_rfc3339(time)),
+        newest: autosaves.first().map(|&(_, time)| format_rfc3339(time)),
+        manual_saves: saves.len() - autosaves.len(),
+    }))
+}
+
+#[derive(Serialize)]
+struct LatestSave {
+    filename: String,
+    modified: String,
+    size: u64,
+    download_url: String,
+}
+
+#[get("/map/<name>/latest.json")]
+async fn latest_save(
+    name: &str,
+    _auth: Authorized,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<Json<LatestSave>, MapError> {
+    let (path, modified_time) = latest_save_file(name, &span, config).await?;
+
+    Ok(Json(LatestSave {
+        filename: path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        modified: format_rfc3339(modified_time),
+        size: path.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        download_url: format!("{}/map/{}", config.base_url, url_encode(name)),
+    }))
+}
+
+// To verify a download, e.g. `curl .../map/MyFactory/sha256` against `sha256sum MyFactory.sav`
+#[get("/map/<name>/sha256")]
+async fn save_sha256(
+    name: &str,
+    _auth: Authorized,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<String, MapError> {
+    let (path, _) = latest_save_file(name, &span, config).await?;
+    let hashes = config.hashes.clone();
+
+    // Streams the file through the hasher, unless the hash of this version is cached
+    rocket::tokio::task::spawn_blocking(move || hashes.sha256(&path))
+        .await
+        .map_err(|e| MapError::Internal(format!("Failed to hash save: {}", e)))?
+        .map_err(|e| MapError::Internal(format!("Failed to read save: {}", e)))
+}
+
+// Cheapest way to poll for changes, the unix time of the latest save as plain text
+#[get("/map/<name>/mtime")]
+async fn save_mtime(
+    name: &str,
+    _auth: Authorized,
+    span: RequestSpan,
+    config: &ServerConfig,
+) -> Result<String, MapError> {
+    let (_, modified_time) = latest_save_file(name, &span, config).await?;
+
+    let secs = modified_time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    Ok(secs.to_string())
+}
+
+// Groups all save files in the save directories by their save name
+fn group_saves(config: &ServerConfig) -> Result<HashMap<String, Vec<PathBuf>>, MapError> {
+    check_save_dirs(config)?;
+    let mut groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
+
+    for path in config.save_files("", true) {
+        let name = path.file_name().and_then(|file_name| {
+            let file_name = file_name.to_string_lossy();
+            let (stem, _) = config.split_extension(&file_name)?;
+            Some(config.save_name(stem).to_string())
+        });
+        // Names that would be rejected when requested aren't listed
+        if let Some(name) = name.filter(|name| validate_name(name).is_ok()) {
+            groups.entry(name).or_default().push(path);
+        }
+    }
+
+    // Directories are read in no particular order
+    for paths in groups.values_mut() {
+        paths.sort();
+    }
+
+    Ok(groups)
+}
+
+#[derive(Serialize)]
+struct SaveSummary {
+    name: String,
+    directory: String,
+    latest_modified: String,
+    file_count: usize,
+    latest_size_bytes: u64,
+    // Kind of the latest file
+    kind: SaveKind,
+}
+
+#[derive(Serialize)]
+struct SavePage {
+    // Number of saves on all pages
+    total: usize,
+    offset: usize,
+    limit: usize,
+    saves: Vec<SaveSummary>,
+}
+
+// Page size of /api/saves when no ?limit= is given
+const DEFAULT_PAGE_LIMIT: usize = 100;
+
+#[get("/api/saves?<limit>&<offset>")]
+fn api_saves(
+    limit: Option<&str>,
+    offset: Option<&str>,
+    _auth: Authorized,
+    config: &ServerConfig,
+) -> Result<Json<SavePage>, MapError> {
+    let limit = page_param("limit", limit, DEFAULT_PAGE_LIMIT)?;
+    let offset = page_param("offset", offset, 0)?;
+
+    // Sorted by name so pages don't shift between requests
+    let mut saves: Vec<_> = group_saves(config)?.into_iter().collect();
+    saves.sort_by(|(a, _), (b, _)| compare_names(a, b));
+    let total = saves.len();
+
+    let mut summaries = Vec::new();
+    for (name, paths) in saves.into_iter().skip(offset).take(limit) {
+        let file_count = paths.len();
+        let latest = paths
+            .iter()
+            .filter_map(|path| path.metadata().ok().map(|metadata| (path, metadata)))
+            .filter_map(|(path, metadata)| {
+                metadata
+                    .modified()
+                    .ok()
+                    .map(|time| (path, time, metadata.len()))
+            })
+            .max_by_key(|&(path, modified_time, _)| (modified_time, path.file_name()));
+
+        if let Some((path, modified_time, size)) = latest {
+            let directory = path
+                .parent()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default();
+            summaries.push(SaveSummary {
+                name,
+                directory,
+                latest_modified: format_rfc3339(modified_time),
+                file_count,
+                latest_size_bytes: size,
+                kind: config.save_kind(path),
+            });
+        }
+    }
+
+    Ok(Json(SavePage {
+        total,
+        offset,
+        limit,
+        saves: summaries,
+    }))
+}
+
+// Parses ?limit= and ?offset=, Rocket would silently ignore invalid numbers
+fn page_param(name: &str, value: Option<&str>, default: usize) -> Result<usize, MapError> {
+    match value {
+        Some(value) => value.parse().map_err(|_| {
+            MapError::BadRequest(format!(
+                "Invalid {} {:?}, expected a non-negative number",
+                name, value
+            ))
+        }),
+        None => Ok(default),
+    }
+}
+
+fn format_rfc3339(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+// Unique save names shown in the listings, sorted case-insensitively so the order is stable
+fn listed_saves(config: &ServerConfig) -> Result<Vec<(String, Vec<PathBuf>)>, MapError> {
+    // Hidden saves are only left out of the list, they can still be downloaded by name
+    let mut saves: Vec<_> = group_saves(config)?
+        .into_iter()
+        .filter(|(name, _)| !config.live.read().unwrap().hidden_names.contains(name))
+        .collect();
+    saves.sort_by(|(a, _), (b, _)| compare_names(a, b));
+    Ok(saves)
+}
+
+// Case-insensitive, with the exact name as a tie-breaker so the order is total
+fn compare_names(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+// One save name per line for shell scripts
+#[get("/map.txt")]
+fn map_txt(_auth: Authorized, config: &ServerConfig) -> Result<String, MapError> {
+    Ok(listed_saves(config)?
+        .into_iter()
+        .map(|(name, _)| name + "\n")
+        .collect())
+}
+
+#[get("/map?<manual>")]
+fn map_index(
+    manual: Option<bool>,
+    _auth: Authorized,
+    config: &ServerConfig,
+) -> Result<IndexHtml, MapError> {
+    let manual = manual.unwrap_or(false);
+    let mut saves = listed_saves(config)?;
+    if manual {
+        for (_, paths) in &mut saves {
+            paths.retain(|path| config.save_kind(path) == SaveKind::Manual);
+        }
+        saves.retain(|(_, paths)| !paths.is_empty());
+    }
+    // The viewer links of the manual listing link to the latest manual saves
+    let manual_params = if manual {
+        vec![("manual", "true".to_string())]
+    } else {
+        Vec::new()
+    };
+
+    let mut total_files = 0;
+    let mut total_size = 0;
+    // Entries with their latest modified time and size, which they are sorted by
+    let mut entries = Vec::new();
+
+    for (save, paths) in saves {
+        let size: u64 = paths
+            .iter()
+            .filter_map(|path| path.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        total_files += paths.len();
+        total_size += size;
+
+        // Report only, growing autosaves usually mean cleanup isn't running
+        let warning = match config.autosave_warn_threshold {
+            Some(threshold) if paths.len() > threshold => {
+                warn!(
+                    "{} has {} files, more than the threshold of {}",
+                    save,
+                    paths.len(),
+                    threshold
+                );
+                format!("{} files, more than {}", paths.len(), threshold)
+            }
+            _ => String::new(),
+        };
+
+        let latest = paths
+            .iter()
+            .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+            .max();
+
+        // A broken latest save only loses its play time, not the whole page
+        let play_time = latest
+            .and_then(|(_, path)| sav_header::parse_header(path).ok())
+            .map(|header| format_play_time(header.play_duration_seconds))
+            .unwrap_or_default();
+
+        let entry = IndexEntry {
+            url: config.viewer_url(&save, &manual_params),
+            name: save,
+            file_count: format_file_count(paths.len()),
+            size: format_mb(size),
+            play_time,
+            warning,
+        };
+        entries.push((entry, latest.map(|(modified, _)| modified), size));
+    }
+
+    // The saves are sorted by name already, which stays the tie-breaker
+    match config.index_sort {
+        IndexSort::Name => {}
+        IndexSort::Recent => entries.sort_by(|(_, a, _), (_, b, _)| b.cmp(a)),
+        IndexSort::Size => entries.sort_by(|(_, _, a), (_, _, b)| b.cmp(a)),
+    }
+    let entries = entries.into_iter().map(|(entry, _, _)| entry).collect();
+
+    let page = IndexPage {
+        title: config.index_title.clone(),
+        entries,
+        total_files: format_file_count(total_files),
+        total_size: format_mb(total_size),
+        generated_at: format_server_time(SystemTime::now()),
+        refresh_secs: config.index_refresh_secs,
+        message: config.index_message.clone(),
+    };
+
+    Ok(render_index(config, &page))
+}
+
+// Listings change with every new save, so they are never cached
+#[derive(Responder)]
+struct IndexHtml {
+    html: RawHtml<String>,
+    cache_control: Header<'static>,
+}
+
+fn render_index(config: &ServerConfig, page: &IndexPage) -> IndexHtml {
+    // Read on every request so template changes show up without a restart
+    let template = match &config.index_template {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(template) => Cow::Owned(template),
+            Err(e) => {
+                warn!(
+                    "Failed to read index template {}, using the built-in one: {}",
+                    path, e
+                );
+                Cow::Borrowed(index_template::DEFAULT_TEMPLATE)
+            }
+        },
+        None => Cow::Borrowed(index_template::DEFAULT_TEMPLATE),
+    };
+
+    IndexHtml {
+        html: RawHtml(index_template::render(&template, page)),
+        cache_control: Header::new("Cache-Control", "no-store"),
+    }
+}
+
+fn format_server_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second] UTC"
+        ))
+        .unwrap_or_default()
+}
+
+fn format_file_count(count: usize) -> String {
+    if count == 1 {
+        "1 file".to_string()
+    } else {
+        format!("{} files", count)
+    }
+}
+
+fn format_play_time(seconds: i32) -> String {
+    let minutes = seconds.max(0) / 60;
+    format!("{}h {}m", minutes / 60, minutes % 60)
+}
+
+fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+// Blueprints are single files, so only the latest file for the name is served
+#[get("/blueprint/<name>")]
+async fn serve_blueprint(
+    name: &str,
+    conditional: Conditional,
+    accept_encoding: AcceptEncoding,
+    _auth: Authorized,
+    _rate_limited: RateLimited,
+    config: &ServerConfig,
+) -> Result<SaveResponse, MapError> {
+    validate_name(name)?;
+
+    // Only `<name>.sbp` itself, not other blueprints starting with the name
+    let exact = blueprint_files(config, name)
+        .into_iter()
+        .filter(|path| path.file_stem().is_some_and(|stem| stem == name))
+        .collect();
+    let Some((path, _)) = sorted_saves(exact).into_iter().next() else {
+        let msg = format!(
+            "No matching files found for {} in {}",
+            name,
+            config.blueprint_dir.as_deref().unwrap_or_default()
+        );
+        warn!("{}", msg);
+        return Err(MapError::NotFound(msg));
+    };
+
+    info!(blueprint_name = name, "Serving file: {}", path.display());
+    let encoding = if config.compress_saves {
+        accept_encoding.preferred()
+    } else {
+        None
+    };
+    let mut save = open_save(&path, format!("{}.sbp", name), encoding).await?;
+    check_file_size(&path, save.size, config)?;
+    save.content_type = config.content_type("sbp");
+
+    Ok(SaveResponse::new(save, &conditional))
+}
+
+#[get("/blueprint")]
+fn blueprint_index(_auth: Authorized, config: &ServerConfig) -> Result<IndexHtml, MapError> {
+    let token_query = config.token_query();
+    let mut blueprints = Vec::new();
+
+    for path in blueprint_files(config, "") {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        let size = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if let Some(name) = name {
+            blueprints.push((name, size));
+        }
+    }
+    blueprints.sort_by(|(a, _), (b, _)| {
+        a.to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b))
+    });
+
+    let total_size = blueprints.iter().map(|(_, size)| size).sum();
+    let page = IndexPage {
+        title: "Blueprints".to_string(),
+        total_files: format_file_count(blueprints.len()),
+        total_size: format_mb(total_size),
+        generated_at: format_server_time(SystemTime::now()),
+        refresh_secs: None,
+        message: None,
+        entries: blueprints
+            .into_iter()
+            .map(|(name, size)| IndexEntry {
+                url: format!(
+                    "{}/blueprint/{}{}",
+                    config.base_url,
+                    url_encode(&name),
+                    token_query
+                ),
+                name,
+                file_count: format_file_count(1),
+                size: format_mb(size),
+                play_time: String::new(),
+                warning: String::new(),
+            })
+            .collect(),
+    };
+
+    Ok(render_index(config, &page))
+}
+
+fn blueprint_files(config: &ServerConfig, prefix: &str) -> Vec<PathBuf> {
+    matching_files(
+        config.blueprint_dir.as_slice(),
+        &["sbp".to_string()],
+        prefix,
+        false,
+        true,
+        config.follow_symlinks,
+    )
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    save_dir_accessible: bool,
+    // Seconds since the server started, and the same as e.g. "1d 2h 3m"
+    uptime_secs: u64,
+    uptime: String,
+}
+
+#[get("/health")]
+fn health(config: &ServerConfig) -> (Status, Json<Health>) {
+    // Re-check on every request so monitoring notices when a save directory disappears
+    let save_dir_accessible = config
+        .save_dirs
+        .iter()
+        .all(|save_dir| fs::read_dir(save_dir).is_ok());
+
+    let (status, name) = if save_dir_accessible {
+        (Status::Ok, "ok")
+    } else {
+        (Status::ServiceUnavailable, "unavailable")
+    };
+    let uptime = config.started_at.elapsed();
+
+    (
+        status,
+        Json(Health {
+            status: name,
+            save_dir_accessible,
+            uptime_secs: uptime.as_secs(),
+            uptime: format_uptime(uptime),
+        }),
+    )
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: &'static str,
+    commit: Option<&'static str>,
+    built_at: String,
+}
+
+// Not protected by the access token, like /health, so deployments can be checked from anywhere
+#[get("/version")]
+fn version() -> Json<Version> {
+    let built_at = env!("SERVE_MAP_BUILD_TIMESTAMP")
+        .parse()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap_or(UNIX_EPOCH);
+
+    Json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: option_env!("SERVE_MAP_GIT_COMMIT"),
+        built_at: format_rfc3339(built_at),
+    })
+}
+
+// Hand-written, so it has to be updated together with the routes and response types
+#[get("/openapi.json")]
+fn openapi() -> (ContentType, &'static str) {
+    (ContentType::JSON, include_str!("openapi.json"))
+}
+
+#[get("/admin")]
+fn admin(_auth: Authorized, config: &ServerConfig) -> Result<IndexHtml, MapError> {
+    let saves = group_saves(config)?;
+    let (total_files, total_size) = saves
+        .values()
+        .flatten()
+        .filter_map(|path| path.metadata().ok())
+        .fold((0, 0), |(files, size), metadata| {
+            (files + 1, size + metadata.len())
+        });
+    let uptime = config.started_at.elapsed();
+
+    let page = AdminPage {
+        title: config.index_title.clone(),
+        save_dirs: config.save_dirs.clone(),
+        save_count: saves.len(),
+        total_files: format_file_count(total_files),
+        total_size: format_mb(total_size),
+        downloads: config.metrics.total_downloads(),
+        bytes_served: format_mb(config.metrics.bytes_served()),
+        uptime: format!("{} ({} seconds)", format_uptime(uptime), uptime.as_secs()),
+        generated_at: format_server_time(SystemTime::now()),
+        recent_downloads: config
+            .metrics
+            .recent_downloads()
+            .into_iter()
+            .map(|download| {
+                (
+                    download.name,
+                    format_mb(download.bytes),
+                    format_server_time(download.at),
+                )
+            })
+            .collect(),
+    };
+
+    Ok(IndexHtml {
+        html: RawHtml(index_template::render_admin(&page)),
+        cache_control: Header::new("Cache-Control", "no-store"),
+    })
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    format!(
+        "{}d {}h {}m",
+        minutes / 1440,
+        minutes / 60 % 24,
+        minutes % 60
+    )
+}
+
+#[derive(Serialize)]
+struct ProfileSummary {
+    name: String,
+    index_url: String,
+}
+
+// Only mounted when profiles are configured
+#[get("/profiles")]
+fn list_profiles(
+    _auth: Authorized,
+    config: &ServerConfig,
+    profiles: &State<Profiles>,
+) -> Json<Vec<ProfileSummary>> {
+    Json(
+        profiles
+            .0
+            .keys()
+            .map(|name| ProfileSummary {
+                name: name.clone(),
+                index_url: format!("{}/{}/map{}", config.base_url, name, config.token_query()),
+            })
+            .collect(),
+    )
+}
+
+#[derive(Serialize)]
+struct Reload {
+    saves: usize,
+}
+
+#[post("/admin/reload")]
+fn admin_reload(
+    _auth: Authorized,
+    config: &ServerConfig,
+    profiles: &State<Profiles>,
+) -> Result<Json<Reload>, MapError> {
+    config.cache.clear();
+    for profile in profiles.0.values() {
+        profile.cache.clear();
+    }
+    let saves = group_saves(config)?.len();
+    info!("Cleared save cache, found {} saves", saves);
+
+    Ok(Json(Reload { saves }))
+}
+
+// Only mounted when enable_metrics is set
+#[get("/metrics")]
+fn serve_metrics(config: &ServerConfig) -> Result<String, MapError> {
+    let saves_present = group_saves(config)?.len();
+    Ok(config.metrics.render(saves_present))
+}
+
+// For people typing the bare hostname, keeps the query so the token still works
+#[get("/")]
+fn root(origin: &Origin<'_>, config: &ServerConfig) -> Redirect {
+    let target = config.root_redirect.as_deref().unwrap_or("/map");
+    match origin.query() {
+        Some(query) if target.contains('?') => Redirect::found(format!("{}&{}", target, query)),
+        Some(query) => Redirect::found(format!("{}?{}", target, query)),
+        None => Redirect::found(target.to_string()),
+    }
+}
+
+// Browsers request this for every page, serve it so it doesn't show up as a 404
+#[get("/favicon.ico")]
+fn favicon() -> (ContentType, &'static [u8]) {
+    (ContentType::Icon, include_bytes!("favicon.ico"))
+}
+
+// Requests the probe filter rewrote, that never reach the handler they were meant for
+#[get("/.rejected")]
+fn rejected_probe(rejected: &Rejected) -> MapError {
+    MapError::BadRequest(format!(
+        "Rejected request for a suspicious path: {}",
+        rejected.reason
+    ))
+}
+
+#[catch(400)]
+fn bad_request() -> MapError {
+    MapError::BadRequest("The request could not be understood".to_string())
+}
+
+#[catch(404)]
+fn not_found(request: &Request<'_>) -> MapError {
+    MapError::NotFound(format!("Nothing found at {}", request.uri().path()))
+}
+
+#[catch(500)]
+fn internal_error() -> MapError {
+    MapError::Internal("Internal server error".to_string())
+}
+
+#[catch(401)]
+fn unauthorized() -> MapError {
+    MapError::Unauthorized("Missing or invalid access token".to_string())
+}
+
+#[catch(429)]
+fn too_many_requests() -> TooManyRequests {
+    TooManyRequests
+}
+
+#[options("/<_..>")]
+fn all_options() {
+    /* Intentionally left empty */
+}
+
+// Request headers clients may send cross-origin
+const ALLOWED_HEADERS: &str =
+    "Authorization, Content-Type, Range, If-Range, If-None-Match, If-Modified-Since";
+
+// Response headers browsers let cross-origin scripts read, besides the safelisted ones
+const EXPOSED_HEADERS: &str =
+    "Content-Disposition, Content-Range, ETag, Retry-After, X-Save-Filename, X-Save-Modified";
+
+pub struct CORS {
+    live: Arc<RwLock<LiveConfig>>,
+    // Joined allowed_methods, e.g. "GET, OPTIONS"
+    allowed_methods: String,
+}
+
+#[rocket::async_trait]
+impl Fairing for CORS {
+    fn info(&self) -> Info {
+        Info {
+            name: "Add CORS headers to responses",
+            kind: Kind::Response,
+        }
+    }
+
+    // Response fairings also run for error responses and catchers, so a 404 reaches the
+    // map viewer with its status instead of failing as a CORS error in the browser
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Echo the request origin if it is allowed, otherwise fall back to the first allowed
+        // origin. Browsers only accept credentials for the exact origin of the request.
+        let live = self.live.read().unwrap();
+        let request_origin = request.headers().get_one("Origin").and_then(|origin| {
+            live.allowed_origins
+                .iter()
+                .find(|allowed| *allowed == origin)
+        });
+
+        if let Some(origin) = request_origin {
+            response.set_header(Header::new("Access-Control-Allow-Origin", origin.clone()));
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        } else if let Some(origin) = live.allowed_origins.first() {
+            response.set_header(Header::new("Access-Control-Allow-Origin", origin.clone()));
+        }
+        // Keep `Vary: Accept-Encoding` of compressed saves
+        let vary = match response.headers().get_one("Vary") {
+            Some(vary) => format!("{}, Origin", vary),
+            None => "Origin".to_string(),
+        };
+        response.set_header(Header::new("Vary", vary));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Methods",
+            self.allowed_methods.clone(),
+        ));
+        // A wildcard isn't allowed together with credentials
+        response.set_header(Header::new("Access-Control-Allow-Headers", ALLOWED_HEADERS));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            EXPOSED_HEADERS,
+        ));
+    }
+}
+
+// Writes, reads back and deletes a small file in every save directory, to catch
+// permission problems and broken volume mounts before serving traffic
+fn selftest(save_dirs: &[String]) -> Result<()> {
+    const CONTENTS: &[u8] = b"satisfactory_serve_map selftest";
+
+    for save_dir in save_dirs {
+        // Not a save extension, so it is never listed or served
+        let path =
+            Path::new(save_dir).join(format!(".serve_map_selftest_{}.tmp", std::process::id()));
+        fs::write(&path, CONTENTS)
+            .with_context(|| format!("Self-test failed, {} is not writable", save_dir))?;
+        let read = fs::read(&path);
+        let removed = fs::remove_file(&path);
+
+        let read =
+            read.with_context(|| format!("Self-test failed, {} is not readable", save_dir))?;
+        if read != CONTENTS {
+            bail!(
+                "Self-test failed, {} returned different contents than written",
+                path.display()
+            );
+        }
+        removed.with_context(|| format!("Self-test failed, can't delete {}", path.display()))?;
+        info!("Self-test passed for {}", save_dir);
+    }
+
+    Ok(())
+}
+
+// Builds the server from the config, ready to launch
+// `config_path` is the --config argument, which hot_reload watches instead of CONFIG_FILES
+fn build(config: Config, config_path: Option<String>) -> Result<Rocket<Build>> {
+    let save_dirs = config.save_dirs();
+    let address: IpAddr = config.address.parse()?;
+
+    if config.startup_selftest {
+        selftest(&save_dirs)?;
+        for profile in config.profiles.values() {
+            selftest(&profile.save_dirs())?;
+        }
+    }
+
+    // Serve HTTPS when both a certificate and a key are configured
+    let tls = config.tls_cert.clone().zip(config.tls_key.clone());
+
+    let live = Arc::new(RwLock::new(LiveConfig::from_config(&config)));
+    let config_watcher = config
+        .hot_reload
+        .then(|| ConfigWatcher::new(config_path, &config, live.clone()));
+    let server_config = ServerConfig {
+        save_dirs,
+        base_url: config.base_url,
+        compress_saves: config.compress_saves,
+        cache: Arc::new(SaveCache::new(
+            Duration::from_secs(config.cache_ttl_secs),
+            config.recursive,
+        )),
+        access_token: config.access_token,
+        file_extensions: config.file_extensions,
+        mime_overrides: config
+            .mime_overrides
+            .iter()
+            .filter_map(|(extension, mime)| {
+                let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+                Some((extension, ContentType::parse_flexible(mime)?))
+            })
+            .collect(),
+        index_template: config.index_template,
+        index_title: config.index_title,
+        index_sort: config.index_sort,
+        rate_limiter: config.max_requests_per_minute.map(RateLimiter::new),
+        // Peers of an IPv6 socket are IPv4-mapped, match them with the plain IPv4 address
+        trusted_proxies: config
+            .trusted_proxies
+            .iter()
+            .map(IpAddr::to_canonical)
+            .collect(),
+        download_limiter: config.max_concurrent_downloads.map(DownloadLimiter::new),
+        download_throttle: config
+            .max_download_bytes_per_sec
+            .map(|rate| DownloadThrottle::new(rate, config.split_download_bandwidth)),
+        parse_saves: config.parse_saves,
+        // The suffixes are written with `_`, match them with the configured separator instead
+        name_suffixes: config
+            .name_suffixes
+            .iter()
+            .map(|suffix| suffix.replace('_', &config.name_separator))
+            .collect(),
+        autosave_suffix: AUTOSAVE_SUFFIX.replace('_', &config.name_separator),
+        live: live.clone(),
+        metrics: Arc::default(),
+        case_insensitive_names: config.case_insensitive_names,
+        blueprint_dir: config.blueprint_dir.clone(),
+        max_file_size_bytes: config.max_file_size_bytes,
+        hashes: Arc::new(HashCache::default()),
+        recursive: config.recursive,
+        autosave_warn_threshold: config.autosave_warn_threshold,
+        index_refresh_secs: config.index_refresh_secs,
+        // An empty value sends no header at all
+        cache_control: config
+            .cache_control
+            .clone()
+            .filter(|value| !value.is_empty()),
+        root_redirect: config
+            .root_redirect
+            .clone()
+            .filter(|target| !target.is_empty()),
+        follow_symlinks: config.follow_symlinks,
+        index_message: config.index_message.clone(),
+        min_save_age: config
+            .serve_stable_only
+            .then(|| Duration::from_secs(config.min_age_secs)),
+        aliases: config.aliases.clone(),
+        started_at: Instant::now(),
+        profile: None,
+    };
+    let profiles = Profiles(
+        config
+            .profiles
+            .iter()
+            .map(|(name, profile)| (name.clone(), server_config.for_profile(name, profile)))
+            .collect(),
+    );
+
+    let figment = rocket::Config::figment()
+        .merge(("address", address))
+        .merge(("port", config.port))
+        // Finish in-flight downloads on SIGTERM or SIGINT before exiting
+        .merge(("shutdown.ctrlc", true))
+        .merge(("shutdown.signals", ["term", "int"]))
+        .merge(("shutdown.grace", config.shutdown_grace_secs))
+        .merge(("shutdown.mercy", 5));
+    let figment = match tls {
+        Some((cert, key)) => figment.merge(("tls.certs", cert)).merge(("tls.key", key)),
+        None => figment,
+    };
+
+    let grace = config.shutdown_grace_secs;
+    let mut rocket = rocket::custom(figment)
+        .attach(RequestLogger)
+        // After the request logger, so the request span still has the original path
+        .attach(ProbeFilter {
+            metrics: server_config.metrics.clone(),
+        })
+        .attach(CORS {
+            live: live.clone(),
+            allowed_methods: config
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse::<Method>().ok())
+                .map(|method| method.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+        .attach(AdHoc::on_shutdown("Log shutdown", move |_| {
+            Box::pin(async move {
+                info!(
+                    "Shutting down, waiting up to {}s for downloads to finish",
+                    grace
+                );
+            })
+        }));
+
+    if let Some(path) = &config.access_log {
+        rocket = rocket.attach(AccessLog::open(path)?);
+    }
+
+    if let Some(config_watcher) = config_watcher {
+        rocket = rocket.attach(config_watcher);
+    }
+
+    if config.watch_saves {
+        for watched in std::iter::once(&server_config).chain(profiles.0.values()) {
+            rocket = rocket.attach(SaveWatcher::new(
+                watched.save_dirs.clone(),
+                watched.file_extensions.clone(),
+                watched.recursive,
+                watched.cache.clone(),
+            ));
+        }
+    }
+
+    if config.enable_metrics {
+        rocket = rocket.mount("/", routes![serve_metrics]);
+    }
+
+    if server_config.root_redirect.is_some() {
+        rocket = rocket.mount("/", routes![root]);
+    }
+
+    // Without the listings save names can't be discovered, only downloaded when known
+    if config.enable_index {
+        rocket = rocket.mount("/", routes![map_index, map_txt, api_saves]);
+        if !profiles.0.is_empty() {
+            rocket = rocket.mount("/", routes![list_profiles]);
+        }
+    }
+
+    // Every profile gets the save routes, which pick its config by the mount point
+    for name in profiles.0.keys() {
+        let base = format!("/{}", name);
+        rocket = rocket.mount(&base, save_routes());
+        if config.enable_index {
+            rocket = rocket.mount(&base, routes![map_index, map_txt, api_saves]);
+        }
+    }
+
+    // Rocket's file server has no request guards, so these files are public
+    if let Some(static_dir) = &config.static_dir {
+        rocket = rocket.mount("/static", FileServer::from(static_dir));
+    }
+
+    if server_config.blueprint_dir.is_some() {
+        rocket = rocket.mount("/", routes![serve_blueprint]);
+        if config.enable_index {
+            rocket = rocket.mount("/", routes![blueprint_index]);
+        }
+    }
+
+    Ok(rocket
+        .mount("/", save_routes())
+        .mount(
+            "/",
+            routes![
+                health,
+                version,
+                openapi,
+                admin,
+                admin_reload,
+                favicon,
+                rejected_probe,
+                all_options
+            ],
+        )
+        .register(
+            "/",
+            catchers![
+                bad_request,
+                unauthorized,
+                not_found,
+                too_many_requests,
+                internal_error
+            ],
+        )
+        .manage(server_config)
+        .manage(profiles))
+}
+
+// Routes serving the saves of a profile, or the top-level saves when mounted at `/`
+fn save_routes() -> Vec<Route> {
+    routes![
+        serve_map,
+        serve_latest,
+        serve_previous,
+        save_info,
+        save_objects,
+        save_versions,
+        save_rotation,
+        save_diff,
+        save_archive,
+        latest_save,
+        save_mtime,
+        save_sha256
+    ]
+}
+
+// Prints a summary and all problems of the config for --check-config, returns whether it is valid
+fn print_config_check(config: &Config, config_path: &str) -> bool {
+    println!("Configuration from {}:", config_path);
+    println!("  Save directories: {}", config.save_dirs().join(", "));
+    println!("  Address: {}", config.bind_address());
+    println!(
+        "  TLS: {}",
+        config.tls_cert.is_some() && config.tls_key.is_some()
+    );
+    println!("  Allowed origins: {}", config.allowed_origins.join(", "));
+    println!("  Allowed methods: {}", config.allowed_methods.join(", "));
+
+    let problems = config.check();
+    for problem in &problems {
+        eprintln!("Error: {}", problem);
+    }
+    if problems.is_empty() {
+        println!("Configuration is valid");
+    }
+    problems.is_empty()
+}
+
+// Path given as `--config <path>` or `--config=<path>`
+fn config_arg(args: &[String]) -> Result<Option<String>> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return match args.next() {
+                Some(path) => Ok(Some(path.clone())),
+                None => bail!("--config requires a path, e.g. --config /etc/serve-map/config.toml"),
+            };
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(Some(path.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[rocket::main]
+async fn main() -> rocket_anyhow::Result {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Only validate the config, e.g. before deploying it
+    let check_only = args.iter().any(|arg| arg == "--check-config");
+    let mut config_arg = match config_arg(&args) {
+        Ok(config_arg) => config_arg,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+    // Deployments from a shared checkout shouldn't pick up someone's config.dev.toml
+    if config_arg.is_none() && args.iter().any(|arg| arg == "--no-dev-config") {
+        let [_, config] = CONFIG_FILES;
+        config_arg = Some(config.to_string());
+    }
+
+    if check_only {
+        let valid = match Config::load_unchecked(config_arg.as_deref()) {
+            Ok((config, config_path)) => print_config_check(&config, &config_path),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                false
+            }
+        };
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
+    // Load configuration, logging isn't set up yet so problems go to stderr
+    let (config, config_path) = match Config::load(config_arg.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    logging::init(&config.log_level, config.log_format)?;
+    info!("Using configuration from {}", config_path);
+    if config_path.starts_with(CONFIG_FILES[0]) {
+        warn!(
+            "Using the development config {}, pass --no-dev-config to only load {}",
+            CONFIG_FILES[0], CONFIG_FILES[1]
+        );
+    }
+    info!("Server starting with configuration:");
+    info!("  Save directories: {}", config.save_dirs().join(", "));
+    for (name, profile) in &config.profiles {
+        info!("  Profile {}: {}", name, profile.save_dirs().join(", "));
+    }
+    if let Some(blueprint_dir) = &config.blueprint_dir {
+        info!("  Blueprint directory: {}", blueprint_dir);
+    }
+    if let Some(static_dir) = &config.static_dir {
+        info!("  Static directory: {}", static_dir);
+    }
+    info!("  Address: {}", config.address);
+    info!("  Port: {}", config.port);
+    info!(
+        "  TLS: {}",
+        config.tls_cert.is_some() && config.tls_key.is_some()
+    );
+    info!("  Base URL: {}", config.base_url);
+    info!("  Compress saves: {}", config.compress_saves);
+    info!("  Cache TTL: {}s", config.cache_ttl_secs);
+    info!("  Allowed origins: {}", config.allowed_origins.join(", "));
+    info!("  Allowed methods: {}", config.allowed_methods.join(", "));
+    info!("  Access token required: {}", config.access_token.is_some());
+    info!("  Log level: {}", config.log_level);
+    info!("  Log format: {:?}", config.log_format);
+    if let Some(path) = &config.access_log {
+        info!("  Access log: {}", path);
+    }
+    info!("  File extensions: {}", config.file_extensions.join(", "));
+    info!("  Recursive: {}", config.recursive);
+    info!("  Follow symlinks: {}", config.follow_symlinks);
+    info!(
+        "  Case-insensitive names: {}",
+        config.case_insensitive_names
+    );
+    info!("  Watch saves: {}", config.watch_saves);
+    info!("  Hot reload: {}", config.hot_reload);
+    info!("  Startup self-test: {}", config.startup_selftest);
+    info!("  Shutdown grace period: {}s", config.shutdown_grace_secs);
+    info!("  Map viewer URL: {}", config.map_viewer_url_template);
+    info!("  Name suffixes: {}", config.name_suffixes.join(", "));
+    info!("  Name separator: {:?}", config.name_separator);
+    info!("  Index sort: {:?}", config.index_sort);
+    if !config.hidden_names.is_empty() {
+        info!("  Hidden from /map: {}", config.hidden_names.join(", "));
+    }
+    if let Some(max) = config.max_file_size_bytes {
+        info!("  Max file size: {} bytes", max);
+    }
+    if let Some(max) = config.max_requests_per_minute {
+        info!("  Max downloads per minute per client: {}", max);
+    }
+    if !config.trusted_proxies.is_empty() {
+        let proxies: Vec<_> = config
+            .trusted_proxies
+            .iter()
+            .map(IpAddr::to_string)
+            .collect();
+        info!("  Trusted proxies: {}", proxies.join(", "));
+    }
+    info!(
+        "  Cache-Control: {}",
+        config.cache_control.as_deref().unwrap_or_default()
+    );
+    if let Some(max) = config.max_concurrent_downloads {
+        info!("  Max concurrent downloads: {}", max);
+    }
+    if config.serve_stable_only {
+        info!(
+            "  Serve stable saves only: older than {}s",
+            config.min_age_secs
+        );
+    }
+    if let Some(rate) = config.max_download_bytes_per_sec {
+        info!(
+            "  Max download rate: {} bytes/s {}",
+            rate,
+            if config.split_download_bandwidth {
+                "shared by all downloads"
+            } else {
+                "per download"
+            }
+        );
+    }
+    info!("Endpoints available:");
+    if let Some(target) = config
+        .root_redirect
+        .as_deref()
+        .filter(|target| !target.is_empty())
+    {
+        info!("  - /               : Redirects to {}", target);
+    }
+    info!("  - /map/<name>     : Serves the latest save file");
+    info!("  - /map/latest     : Serves the newest save file of any save name");
+    info!("  - /map/<name>/previous: Serves the save file before the latest one");
+    info!("  - /map/<name>/info: Serves the header of the latest save file");
+    info!("  - /map/<name>/objects: Serves object counts parsed from the latest save file");
+    info!("  - /map/<name>/versions: Serves a JSON list of all versions of a save");
+    info!("  - /map/<name>/rotation: Serves which autosave slots of a save are in use");
+    info!("  - /map/<name>/diff: Compares the size and time of the two newest files of a save");
+    info!("  - /map/<name>/archive.zip: Serves all versions of a save as a zip archive");
+    info!("  - /map/<name>/latest.json: Serves metadata about the latest save file");
+    info!("  - /map/<name>/mtime: Serves the modified time of the latest save file");
+    info!("  - /map/<name>/sha256: Serves the SHA-256 of the latest save file");
+    if config.enable_index {
+        info!("  - /map            : Serves a list of available maps");
+        info!("  - /map.txt        : Serves a plain text list of save names");
+        info!("  - /api/saves      : Serves a JSON list of available maps");
+    }
+    if !config.profiles.is_empty() {
+        info!("  - /<profile>/map/...: Serves the saves of a profile like the routes above");
+        if config.enable_index {
+            info!("  - /profiles       : Serves a JSON list of the profiles");
+        }
+    }
+    info!("  - /health         : Reports whether the save directories are accessible");
+    info!("  - /version        : Reports the version, git commit and build time");
+    info!("  - /openapi.json   : Serves an OpenAPI description of the endpoints");
+    info!("  - /admin          : Serves an overview of the server state");
+    info!("  - POST /admin/reload: Clears the save cache and rescans the save directories");
+    if config.blueprint_dir.is_some() {
+        info!("  - /blueprint/<name>: Serves the blueprint file with the given name");
+        if config.enable_index {
+            info!("  - /blueprint      : Serves a list of available blueprints");
+        }
+    }
+    if config.enable_metrics {
+        info!("  - /metrics        : Serves download counters in the Prometheus format");
+    }
+    if config.static_dir.is_some() {
+        info!("  - /static/<path>  : Serves the files in the static directory");
+    }
+
+    let bind_address = config.bind_address();
+    let rocket = match build(config, config_arg) {
+        Ok(rocket) => rocket,
+        Err(e) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = rocket.launch().await {
+        match e.kind() {
+            rocket::error::ErrorKind::Bind(e) => {
+                error!("Failed to bind to {}: {}", bind_address, e)
+            }
+            _ => error!("{}", e),
+        }
+        std::process::exit(1);
+    }
+
+    info!("Shutdown complete");
     Ok(())
 }
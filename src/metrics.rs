@@ -0,0 +1,121 @@
+//! Download counters exposed in the Prometheus text format at `/metrics`.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+// Number of downloads kept for the /admin page
+const RECENT_DOWNLOADS: usize = 20;
+
+#[derive(Default)]
+pub struct Metrics {
+    downloads: RwLock<BTreeMap<String, AtomicU64>>,
+    bytes_served: AtomicU64,
+    // Requests the probe filter answered with a 400
+    rejected: AtomicU64,
+    // Newest first
+    recent: Mutex<VecDeque<RecentDownload>>,
+}
+
+#[derive(Clone)]
+pub struct RecentDownload {
+    pub name: String,
+    pub bytes: u64,
+    pub at: SystemTime,
+}
+
+impl Metrics {
+    pub fn record_download(&self, name: &str, bytes: u64) {
+        self.bytes_served.fetch_add(bytes, Ordering::Relaxed);
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_front(RecentDownload {
+            name: name.to_string(),
+            bytes,
+            at: SystemTime::now(),
+        });
+        recent.truncate(RECENT_DOWNLOADS);
+        drop(recent);
+
+        // Most downloads are for names seen before, which only need the read lock
+        if let Some(count) = self.downloads.read().unwrap().get(name) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.downloads
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn total_downloads(&self) -> u64 {
+        self.downloads
+            .read()
+            .unwrap()
+            .values()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn bytes_served(&self) -> u64 {
+        self.bytes_served.load(Ordering::Relaxed)
+    }
+
+    pub fn recent_downloads(&self) -> Vec<RecentDownload> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn render(&self, saves_present: usize) -> String {
+        let mut text = String::new();
+
+        text.push_str("# HELP saves_downloads_total Number of save downloads per save name\n");
+        text.push_str("# TYPE saves_downloads_total counter\n");
+        for (name, count) in self.downloads.read().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "saves_downloads_total{{name=\"{}\"}} {}",
+                escape_label(name),
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        text.push_str("# HELP saves_bytes_served_total Uncompressed size of all served saves\n");
+        text.push_str("# TYPE saves_bytes_served_total counter\n");
+        let _ = writeln!(
+            text,
+            "saves_bytes_served_total {}",
+            self.bytes_served.load(Ordering::Relaxed)
+        );
+
+        text.push_str(
+            "# HELP requests_rejected_total Requests rejected as probes, like path traversal attempts\n",
+        );
+        text.push_str("# TYPE requests_rejected_total counter\n");
+        let _ = writeln!(
+            text,
+            "requests_rejected_total {}",
+            self.rejected.load(Ordering::Relaxed)
+        );
+
+        text.push_str("# HELP saves_present Number of distinct save names currently available\n");
+        text.push_str("# TYPE saves_present gauge\n");
+        let _ = writeln!(text, "saves_present {}", saves_present);
+
+        text
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,110 @@
+//! Fairing that rejects requests for paths no client of the server sends, like path traversal
+//! attempts, before they are routed to a handler.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Method, RawStr, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request};
+use tracing::warn;
+
+use crate::logging;
+use crate::metrics::Metrics;
+
+// Longest path accepted, real ones are a save name and a few fixed segments
+const MAX_PATH_LEN: usize = 1024;
+// Longest path segment accepted, most file systems don't allow longer file names
+const MAX_SEGMENT_LEN: usize = 255;
+
+/// Rejected requests are rewritten to this path, which answers with a `400`
+pub const REJECTED_PATH: &str = "/.rejected";
+
+/// Method and path a rejected request was sent with, before it was rewritten
+pub struct Rejected {
+    pub method: Method,
+    pub path: String,
+    pub reason: String,
+}
+
+pub struct ProbeFilter {
+    pub metrics: Arc<Metrics>,
+}
+
+#[rocket::async_trait]
+impl Fairing for ProbeFilter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Reject suspicious paths",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let Some(reason) = probe_reason(request.uri().path().as_str()) else {
+            return;
+        };
+
+        // The request span was created by the request logger and still has the original path
+        logging::request_span(request)
+            .in_scope(|| warn!("Rejected suspicious request: {}", reason));
+        self.metrics.record_rejected();
+
+        let rejected = Rejected {
+            method: request.method(),
+            path: request.uri().path().to_string(),
+            reason,
+        };
+        request.local_cache(|| Some(rejected));
+        request.set_method(Method::Get);
+        request.set_uri(Origin::path_only(REJECTED_PATH));
+    }
+}
+
+/// The original request, `None` unless it was rejected
+pub fn rejected<'r>(request: &'r Request<'_>) -> Option<&'r Rejected> {
+    request.local_cache(|| None::<Rejected>).as_ref()
+}
+
+/// Request guard for the route at [`REJECTED_PATH`], which forwards requests the fairing
+/// didn't rewrite so asking for the path directly is a `404`
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Rejected {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match rejected(request) {
+            Some(rejected) => Outcome::Success(rejected),
+            None => Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+// Why the path looks like a probe, checked on the raw path since Rocket decodes parameters
+fn probe_reason(path: &str) -> Option<String> {
+    if path.len() > MAX_PATH_LEN {
+        return Some(format!("path is longer than {} bytes", MAX_PATH_LEN));
+    }
+
+    for segment in path.split('/') {
+        let Ok(decoded) = RawStr::new(segment).percent_decode() else {
+            return Some("path isn't valid UTF-8".to_string());
+        };
+        if decoded == ".." || decoded == "." {
+            return Some("path traversal".to_string());
+        }
+        if decoded.contains(['/', '\\', '\0']) {
+            return Some("encoded path separator".to_string());
+        }
+        if decoded.len() > MAX_SEGMENT_LEN {
+            return Some(format!(
+                "path segment is longer than {} bytes",
+                MAX_SEGMENT_LEN
+            ));
+        }
+    }
+
+    None
+}
@@ -0,0 +1,35 @@
+//! Request guard for the config of the profile a route is mounted for.
+
+use std::collections::BTreeMap;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::ServerConfig;
+
+/// Configs of the profiles, by name. The top-level config is managed on its own.
+pub struct Profiles(pub BTreeMap<String, ServerConfig>);
+
+/// Routes of a profile are mounted at `/<profile>`, everything mounted at `/` gets the
+/// top-level config. Routes without a profile, like `/health`, always get the latter.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r ServerConfig {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let base = request.route().map_or("/", |route| route.uri.base());
+
+        let config = match base.trim_start_matches('/') {
+            "" => rocket.state::<ServerConfig>(),
+            profile => rocket
+                .state::<Profiles>()
+                .and_then(|profiles| profiles.0.get(profile)),
+        };
+        match config {
+            Some(config) => Outcome::Success(config),
+            None => Outcome::Error((Status::InternalServerError, "Missing server config")),
+        }
+    }
+}
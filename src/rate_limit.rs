@@ -0,0 +1,126 @@
+//! Per client IP token bucket rate limiting for save downloads.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+use crate::{client_ip, ServerConfig};
+
+// Buckets untouched for this long have refilled completely and can be dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    max_requests_per_minute: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    last_prune: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests_per_minute: u32) -> Self {
+        RateLimiter {
+            max_requests_per_minute,
+            buckets: Mutex::new(HashMap::new()),
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Takes a token for the client, or returns how long until the next one is available
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let capacity = self.max_requests_per_minute as f64;
+        let refill_per_sec = capacity / 60.0;
+
+        self.prune(now);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / refill_per_sec,
+            ))
+        }
+    }
+
+    fn prune(&self, now: Instant) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if now.duration_since(*last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        *last_prune = now;
+
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| now.duration_since(bucket.updated) < PRUNE_INTERVAL);
+    }
+}
+
+// Seconds until the client may retry, stored for the 429 catcher
+struct RetryAfter(Option<u64>);
+
+/// Request guard that fails with 429 Too Many Requests when the client exceeded the rate limit
+pub struct RateLimited;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimited {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = request
+            .rocket()
+            .state::<ServerConfig>()
+            .and_then(|config| config.rate_limiter.as_ref());
+
+        let (Some(limiter), Some(ip)) = (limiter, client_ip::client_ip(request)) else {
+            return Outcome::Success(RateLimited);
+        };
+
+        match limiter.check(ip) {
+            Ok(()) => Outcome::Success(RateLimited),
+            Err(retry_after) => {
+                // Round up so clients don't retry before a token is available
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                request.local_cache(|| RetryAfter(Some(secs)));
+                Outcome::Error((Status::TooManyRequests, ()))
+            }
+        }
+    }
+}
+
+/// Response for the 429 catcher, includes `Retry-After` when known
+pub struct TooManyRequests;
+
+impl<'r> Responder<'r, 'static> for TooManyRequests {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response.status(Status::TooManyRequests);
+
+        if let RetryAfter(Some(secs)) = request.local_cache(|| RetryAfter(None)) {
+            response.header(Header::new("Retry-After", secs.to_string()));
+        }
+
+        response.ok()
+    }
+}
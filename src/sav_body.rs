@@ -0,0 +1,267 @@
+//! Best-effort parser for the compressed body of Satisfactory `.sav` files.
+//!
+//! The body following the header is split into zlib compressed chunks. Once
+//! decompressed it contains the save's levels, each with a block of object headers
+//! followed by the serialized object data. Only the object headers are read, which
+//! is enough for high-level counts. Chunks are decompressed one at a time, so even
+//! very large saves never have to fit into memory.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+
+use crate::sav_header::{read_header, read_i32, read_i64, read_string, read_u8};
+
+// Tag at the start of every compressed chunk
+const PACKAGE_FILE_TAG: [u8; 4] = [0xC1, 0x83, 0x2A, 0x9E];
+// Marks the newer chunk header that also stores the compression algorithm
+const ARCHIVE_V2_HEADER: i32 = 0x22222222;
+// Compression algorithm id for zlib in the newer chunk header
+const COMPRESSION_ZLIB: u8 = 3;
+
+// Save version that moved to Unreal Engine 5 and 64 bit length prefixes
+const SAVE_VERSION_UE5: i32 = 41;
+// Save version that added world partition grids before the levels
+const SAVE_VERSION_WORLD_PARTITION: i32 = 46;
+
+// Limits that protect against huge allocations or endless loops on junk files
+const MAX_HEADER_SCAN: usize = 1024 * 1024;
+const MAX_CHUNK_SIZE: i64 = 64 * 1024 * 1024;
+const MAX_COUNT: i32 = 10_000_000;
+
+const OBJECT_TYPE_ACTOR: i32 = 1;
+const OBJECT_TYPE_COMPONENT: i32 = 0;
+
+#[derive(Serialize, Debug)]
+pub struct SaveObjects {
+    pub session_name: String,
+    pub level_count: usize,
+    pub object_count: usize,
+    pub actor_count: usize,
+    pub component_count: usize,
+    pub actors_per_class: BTreeMap<String, usize>,
+}
+
+pub fn parse_objects(path: &Path) -> Result<SaveObjects> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+
+    read_objects(&mut reader).with_context(|| format!("Failed to parse body of {}", path.display()))
+}
+
+fn read_objects(reader: &mut BufReader<File>) -> Result<SaveObjects> {
+    let header = read_header(reader)?;
+    let save_version = header.save_version;
+
+    // Newer headers have more fields than we parse, so look for the first chunk instead
+    skip_to_first_chunk(reader)?;
+    let mut body = BufReader::new(ChunkReader {
+        inner: reader,
+        buf: Vec::new(),
+        pos: 0,
+        tag_consumed: true,
+    });
+
+    let _body_size = read_len(&mut body, save_version)?;
+
+    if save_version >= SAVE_VERSION_WORLD_PARTITION {
+        skip_grids(&mut body)?;
+    }
+
+    let level_count = read_count(&mut body)?;
+
+    let mut objects = SaveObjects {
+        session_name: header.session_name,
+        level_count: level_count + 1,
+        object_count: 0,
+        actor_count: 0,
+        component_count: 0,
+        actors_per_class: BTreeMap::new(),
+    };
+
+    // Sub levels come first, the persistent level without a name is last
+    for level in 0..=level_count {
+        if level < level_count {
+            let _level_name = read_string(&mut body)?;
+        }
+
+        let headers_len = read_len(&mut body, save_version)?;
+        let mut headers = (&mut body).take(headers_len);
+        read_object_headers(&mut headers, &mut objects)
+            .with_context(|| format!("Failed to read object headers of level {}", level))?;
+        // The rest of the header block lists collected objects
+        io::copy(&mut headers, &mut io::sink())?;
+
+        if level == level_count {
+            break;
+        }
+
+        let objects_len = read_len(&mut body, save_version)?;
+        let skipped = io::copy(&mut (&mut body).take(objects_len), &mut io::sink())?;
+        if skipped != objects_len {
+            bail!("Unexpected end of file in objects of level {}", level);
+        }
+
+        // References to destroyed actors
+        for _ in 0..read_count(&mut body)? {
+            let _level_name = read_string(&mut body)?;
+            let _path_name = read_string(&mut body)?;
+        }
+    }
+
+    Ok(objects)
+}
+
+fn read_object_headers(reader: &mut impl Read, objects: &mut SaveObjects) -> Result<()> {
+    let count = read_count(reader)?;
+
+    for _ in 0..count {
+        let object_type = read_i32(reader)?;
+        let class_name = read_string(reader)?;
+        let _level_name = read_string(reader)?;
+        let _path_name = read_string(reader)?;
+
+        match object_type {
+            OBJECT_TYPE_ACTOR => {
+                // Transform flag, rotation, translation, scale and placed in level flag
+                let mut transform = [0; 4 + 4 * 10 + 4];
+                reader
+                    .read_exact(&mut transform)
+                    .context("Unexpected end of file")?;
+                objects.actor_count += 1;
+                *objects.actors_per_class.entry(class_name).or_default() += 1;
+            }
+            OBJECT_TYPE_COMPONENT => {
+                let _parent_actor = read_string(reader)?;
+                objects.component_count += 1;
+            }
+            _ => bail!("Unknown object type {}", object_type),
+        }
+        objects.object_count += 1;
+    }
+
+    Ok(())
+}
+
+fn skip_grids(reader: &mut impl Read) -> Result<()> {
+    for _ in 0..read_count(reader)? {
+        let _grid_name = read_string(reader)?;
+        let _cell_size = read_i32(reader)?;
+        let _grid_hash = read_i32(reader)?;
+        for _ in 0..read_count(reader)? {
+            let _level_name = read_string(reader)?;
+            let _level_hash = read_i32(reader)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_len(reader: &mut impl Read, save_version: i32) -> Result<u64> {
+    let len = if save_version >= SAVE_VERSION_UE5 {
+        read_i64(reader)?
+    } else {
+        read_i32(reader)? as i64
+    };
+    u64::try_from(len).with_context(|| format!("Invalid length {}", len))
+}
+
+fn read_count(reader: &mut impl Read) -> Result<usize> {
+    let count = read_i32(reader)?;
+    if !(0..=MAX_COUNT).contains(&count) {
+        bail!("Count {} out of range", count);
+    }
+    Ok(count as usize)
+}
+
+fn skip_to_first_chunk(reader: &mut impl Read) -> Result<()> {
+    let mut window = [0; 4];
+    for _ in 0..MAX_HEADER_SCAN {
+        window.rotate_left(1);
+        window[3] = read_u8(reader).context("No compressed save body found")?;
+        if window == PACKAGE_FILE_TAG {
+            return Ok(());
+        }
+    }
+    bail!("No compressed save body found")
+}
+
+/// Reader over the decompressed contents of consecutive compressed chunks
+struct ChunkReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    // Whether the tag of the next chunk was already read
+    tag_consumed: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    // Decompresses the next chunk into the buffer, returns false at the end of the file
+    fn next_chunk(&mut self) -> Result<bool> {
+        if !self.tag_consumed {
+            let mut tag = [0; 4];
+            match self.inner.read_exact(&mut tag) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+            if tag != PACKAGE_FILE_TAG {
+                bail!("Invalid chunk tag {:02x?}", tag);
+            }
+        }
+        self.tag_consumed = false;
+
+        if read_i32(&mut self.inner)? == ARCHIVE_V2_HEADER {
+            let _max_chunk_size = read_i64(&mut self.inner)?;
+            let algorithm = read_u8(&mut self.inner)?;
+            if algorithm != COMPRESSION_ZLIB {
+                bail!("Unsupported compression algorithm {}", algorithm);
+            }
+        } else {
+            let _max_chunk_size = read_i64(&mut self.inner)?;
+        }
+
+        let compressed_size = read_i64(&mut self.inner)?;
+        let uncompressed_size = read_i64(&mut self.inner)?;
+        // The sizes are repeated for the single block of the chunk
+        let _ = read_i64(&mut self.inner)?;
+        let _ = read_i64(&mut self.inner)?;
+
+        if !(0..=MAX_CHUNK_SIZE).contains(&compressed_size)
+            || !(0..=MAX_CHUNK_SIZE).contains(&uncompressed_size)
+        {
+            bail!("Chunk size out of range");
+        }
+
+        self.buf.clear();
+        self.pos = 0;
+        let mut compressed = (&mut self.inner).take(compressed_size as u64);
+        ZlibDecoder::new(&mut compressed)
+            .take(uncompressed_size as u64)
+            .read_to_end(&mut self.buf)
+            .context("Failed to decompress chunk")?;
+        // Skip any trailing bytes so the next chunk starts at the right position
+        io::copy(&mut compressed, &mut io::sink())?;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if !self.next_chunk().map_err(io::Error::other)? {
+                return Ok(0);
+            }
+        }
+
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,186 @@
+//! Parser for the uncompressed header at the start of Satisfactory `.sav` files.
+//!
+//! Only the fixed header fields are read, the compressed save body is never touched.
+//! Fields were added to the header over time, so which fields are present depends
+//! on the save header version stored in the first four bytes of the file.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+// Upper bound for string lengths, protects against allocating huge buffers for junk files
+const MAX_STRING_LEN: i32 = 64 * 1024;
+
+// Header version that added the session visibility byte
+const VERSION_SESSION_VISIBILITY: i32 = 5;
+// Header version that added the editor object version
+const VERSION_EDITOR_OBJECT_VERSION: i32 = 7;
+// Header version that added mod metadata and the modded flag
+const VERSION_MOD_METADATA: i32 = 8;
+// Header version that added the save identifier
+const VERSION_SAVE_IDENTIFIER: i32 = 10;
+// Header version that added the save name before the map name
+const VERSION_SAVE_NAME: i32 = 14;
+
+#[derive(Serialize, Debug)]
+pub struct SaveHeader {
+    pub header_version: i32,
+    pub save_version: i32,
+    pub build_version: i32,
+    pub save_name: Option<String>,
+    pub map_name: String,
+    pub starting_location: Option<String>,
+    pub session_name: String,
+    pub play_duration_seconds: i32,
+    pub save_date_time_ticks: i64,
+    pub session_visibility: Option<u8>,
+    pub editor_object_version: Option<i32>,
+    pub is_modded_save: Option<bool>,
+    pub save_identifier: Option<String>,
+}
+
+pub fn parse_header(path: &Path) -> Result<SaveHeader> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+
+    read_header(&mut reader)
+        .with_context(|| format!("Failed to parse header of {}", path.display()))
+}
+
+pub(crate) fn read_header(reader: &mut impl Read) -> Result<SaveHeader> {
+    let header_version = read_i32(reader)?;
+    if header_version < 0 {
+        bail!("Invalid save header version {}", header_version);
+    }
+
+    let save_version = read_i32(reader)?;
+    let build_version = read_i32(reader)?;
+
+    let save_name = if header_version >= VERSION_SAVE_NAME {
+        Some(read_string(reader)?)
+    } else {
+        None
+    };
+
+    let map_name = read_string(reader)?;
+    let map_options = read_string(reader)?;
+    let starting_location = map_option(&map_options, "startloc");
+    let session_name = read_string(reader)?;
+    let play_duration_seconds = read_i32(reader)?;
+    let save_date_time_ticks = read_i64(reader)?;
+
+    let session_visibility = if header_version >= VERSION_SESSION_VISIBILITY {
+        Some(read_u8(reader)?)
+    } else {
+        None
+    };
+
+    let editor_object_version = if header_version >= VERSION_EDITOR_OBJECT_VERSION {
+        Some(read_i32(reader)?)
+    } else {
+        None
+    };
+
+    let is_modded_save = if header_version >= VERSION_MOD_METADATA {
+        let _mod_metadata = read_string(reader)?;
+        Some(read_i32(reader)? != 0)
+    } else {
+        None
+    };
+
+    let save_identifier = if header_version >= VERSION_SAVE_IDENTIFIER {
+        Some(read_string(reader)?)
+    } else {
+        None
+    };
+
+    Ok(SaveHeader {
+        header_version,
+        save_version,
+        build_version,
+        save_name,
+        map_name,
+        starting_location,
+        session_name,
+        play_duration_seconds,
+        save_date_time_ticks,
+        session_visibility,
+        editor_object_version,
+        is_modded_save,
+        save_identifier,
+    })
+}
+
+// Map options are a URL style query like "?startloc=Grass Fields?sessionName=Foo",
+// values are passed through as is so starting areas of map mods still show up
+fn map_option(map_options: &str, key: &str) -> Option<String> {
+    map_options
+        .split('?')
+        .filter_map(|option| option.split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+pub(crate) fn read_u8(reader: &mut impl Read) -> Result<u8> {
+    let mut buf = [0; 1];
+    reader
+        .read_exact(&mut buf)
+        .context("Unexpected end of file")?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_i32(reader: &mut impl Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    reader
+        .read_exact(&mut buf)
+        .context("Unexpected end of file")?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_i64(reader: &mut impl Read) -> Result<i64> {
+    let mut buf = [0; 8];
+    reader
+        .read_exact(&mut buf)
+        .context("Unexpected end of file")?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+// Reads an Unreal FString: a signed length followed by a null terminated string,
+// where a negative length means the string is UTF-16 encoded
+pub(crate) fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = read_i32(reader)?;
+    if len == 0 {
+        return Ok(String::new());
+    }
+    if !(-MAX_STRING_LEN..=MAX_STRING_LEN).contains(&len) {
+        bail!("String length {} out of range", len);
+    }
+
+    let mut string = if len > 0 {
+        let mut buf = vec![0; len as usize];
+        reader
+            .read_exact(&mut buf)
+            .context("Unexpected end of file")?;
+        buf.iter().map(|&byte| byte as char).collect::<String>()
+    } else {
+        let mut buf = vec![0; len.unsigned_abs() as usize * 2];
+        reader
+            .read_exact(&mut buf)
+            .context("Unexpected end of file")?;
+        let units: Vec<u16> = buf
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    // Strip the null terminator
+    if string.ends_with('\0') {
+        string.pop();
+    }
+    Ok(string)
+}
@@ -0,0 +1,116 @@
+//! In-memory cache of save lookups keyed by save name.
+//!
+//! Entries are invalidated when the modified time of any save directory changes,
+//! which happens whenever a save is added, removed or renamed, or after a TTL. When
+//! subdirectories are searched their modified times count as well.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+struct CacheEntry {
+    saves: Vec<(PathBuf, SystemTime)>,
+    dir_modified: BTreeMap<PathBuf, Option<SystemTime>>,
+    inserted: Instant,
+}
+
+pub struct SaveCache {
+    ttl: Duration,
+    recursive: bool,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl SaveCache {
+    pub fn new(ttl: Duration, recursive: bool) -> Self {
+        SaveCache {
+            ttl,
+            recursive,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the cached saves for the name if still valid
+    pub fn get(&self, name: &str, save_dirs: &[String]) -> Option<Vec<(PathBuf, SystemTime)>> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(name)?;
+
+        if entry.inserted.elapsed() >= self.ttl
+            || entry.dir_modified != dir_modified(save_dirs, self.recursive)
+        {
+            return None;
+        }
+
+        Some(entry.saves.clone())
+    }
+
+    pub fn insert(&self, name: &str, save_dirs: &[String], saves: Vec<(PathBuf, SystemTime)>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        self.entries.lock().unwrap().insert(
+            name.to_string(),
+            CacheEntry {
+                saves,
+                dir_modified: dir_modified(save_dirs, self.recursive),
+                inserted: Instant::now(),
+            },
+        );
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Drops all entries whose name is a prefix of the file name, ignoring case, as their
+    /// lookups may now resolve differently
+    pub fn invalidate_file(&self, file_name: &str) {
+        let file_name = file_name.to_lowercase();
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|name, _| !file_name.starts_with(&name.to_lowercase()));
+    }
+}
+
+// Modified times of the save directories, and of all their subdirectories when recursive
+fn dir_modified(save_dirs: &[String], recursive: bool) -> BTreeMap<PathBuf, Option<SystemTime>> {
+    let mut modified = BTreeMap::new();
+    let mut pending: Vec<PathBuf> = save_dirs.iter().map(PathBuf::from).collect();
+    // Directories reached again through a symlink are only read once, like the lookups do
+    let mut visited = HashSet::new();
+
+    while let Some(dir) = pending.pop() {
+        if !visited.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        modified.insert(
+            dir.clone(),
+            fs::metadata(&dir).and_then(|m| m.modified()).ok(),
+        );
+        if !recursive {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        pending.extend(
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir()),
+        );
+    }
+
+    modified
+}
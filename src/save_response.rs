@@ -0,0 +1,451 @@
+//! Responder for save downloads with support for conditional requests (ETag and Last-Modified),
+//! single byte range requests and on the fly compression.
+
+use std::convert::Infallible;
+use std::io::{Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_compression::tokio::bufread::{GzipEncoder, ZlibEncoder};
+use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncReadExt, BufReader};
+use rocket::tokio::sync::OwnedSemaphorePermit;
+use rocket::Request;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::access_log::SaveBytes;
+use crate::download_limit::Permitted;
+use crate::throttle::{ThrottleSlot, Throttled};
+
+/// Conditional and range request headers sent by the client
+pub struct Conditional {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+    range: Option<String>,
+    if_range: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Conditional {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Conditional {
+            if_none_match: request.headers().get_one("If-None-Match").map(String::from),
+            if_modified_since: request
+                .headers()
+                .get_one("If-Modified-Since")
+                .and_then(|date| httpdate::parse_http_date(date).ok()),
+            range: request.headers().get_one("Range").map(String::from),
+            if_range: request.headers().get_one("If-Range").map(String::from),
+        })
+    }
+}
+
+impl Conditional {
+    // Whether the client already has the version identified by the etag
+    fn matches_etag(&self, etag: &str) -> bool {
+        let Some(if_none_match) = &self.if_none_match else {
+            return false;
+        };
+
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+    }
+
+    // Whether the client's cached copy is at least as new as the file
+    fn not_modified_since(&self, modified: SystemTime) -> bool {
+        let Some(if_modified_since) = self.if_modified_since else {
+            return false;
+        };
+
+        // HTTP dates only have second granularity
+        if_modified_since >= truncate_to_secs(modified)
+    }
+
+    fn is_not_modified(&self, etag: &str, modified: SystemTime) -> bool {
+        // If-None-Match takes precedence when both are sent
+        if self.if_none_match.is_some() {
+            self.matches_etag(etag)
+        } else {
+            self.not_modified_since(modified)
+        }
+    }
+
+    // The requested byte range, `None` when the whole file should be sent
+    fn byte_range(&self, etag: &str, modified: SystemTime, size: u64) -> Option<ByteRange> {
+        let range = self.range.as_deref()?;
+
+        // A stale If-Range means the client's partial copy is outdated, so send everything
+        if let Some(if_range) = &self.if_range {
+            let fresh = if if_range.starts_with('"') {
+                if_range == etag
+            } else {
+                httpdate::parse_http_date(if_range)
+                    .is_ok_and(|date| date == truncate_to_secs(modified))
+            };
+            if !fresh {
+                return None;
+            }
+        }
+
+        parse_range(range, size)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ByteRange {
+    // Inclusive start and end offsets
+    Satisfiable(u64, u64),
+    NotSatisfiable,
+}
+
+// Parses a single `bytes=` range, multiple ranges and invalid headers are ignored
+fn parse_range(range: &str, size: u64) -> Option<ByteRange> {
+    let spec = range.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = if start.is_empty() {
+        // Suffix range with the number of bytes at the end of the file
+        let len: u64 = end.parse().ok()?;
+        if len == 0 || size == 0 {
+            return Some(ByteRange::NotSatisfiable);
+        }
+        (size.saturating_sub(len), size - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            end.parse().ok()?
+        };
+        if end < start {
+            return None;
+        }
+        if start >= size {
+            return Some(ByteRange::NotSatisfiable);
+        }
+        (start, end.min(size - 1))
+    };
+
+    Some(ByteRange::Satisfiable(start, end))
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Content encodings the client accepts, parsed from `Accept-Encoding`
+pub struct AcceptEncoding {
+    gzip: bool,
+    deflate: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptEncoding {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let mut accept = AcceptEncoding {
+            gzip: false,
+            deflate: false,
+        };
+
+        for value in request.headers().get("Accept-Encoding") {
+            for coding in value.split(',') {
+                let mut parts = coding.split(';').map(str::trim);
+                let name = parts.next().unwrap_or_default();
+                // Codings with a quality of zero are explicitly not acceptable
+                let rejected = parts.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                });
+
+                match name {
+                    "gzip" => accept.gzip = !rejected,
+                    "deflate" => accept.deflate = !rejected,
+                    _ => {}
+                }
+            }
+        }
+
+        Outcome::Success(accept)
+    }
+}
+
+impl AcceptEncoding {
+    pub fn accepts_gzip(&self) -> bool {
+        self.gzip
+    }
+
+    // Picks the preferred encoding the client accepts
+    pub fn preferred(&self) -> Option<Encoding> {
+        if self.gzip {
+            Some(Encoding::Gzip)
+        } else if self.deflate {
+            Some(Encoding::Deflate)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Computes an etag from the modified time and size of a file
+pub fn etag(modified: SystemTime, size: u64) -> String {
+    let nanos = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", nanos, size)
+}
+
+/// A save file ready to be sent to the client
+pub struct SaveFile {
+    pub file: NamedFile,
+    pub etag: String,
+    pub modified: SystemTime,
+    pub size: u64,
+    /// File name suggested to the client, without the internal timestamped path
+    pub download_name: String,
+    pub content_type: ContentType,
+    /// Name of the served file in the save directory, sent as `X-Save-Filename`
+    pub file_name: String,
+    pub encoding: Option<Encoding>,
+    /// Whether the file is already compressed with the encoding, like a `.sav.gz`
+    pub precompressed: bool,
+    /// Download slot released once the body was sent, `None` without a download limit
+    pub permit: Option<OwnedSemaphorePermit>,
+    /// Bandwidth cap of the body, `None` without max_download_bytes_per_sec
+    pub throttle: Option<ThrottleSlot>,
+    pub cache_control: Option<String>,
+}
+
+pub enum SaveResponse {
+    File(SaveFile),
+    Partial {
+        save: SaveFile,
+        start: u64,
+        end: u64,
+    },
+    RangeNotSatisfiable {
+        size: u64,
+    },
+    NotModified {
+        etag: String,
+        modified: SystemTime,
+        cache_control: Option<String>,
+    },
+}
+
+impl SaveResponse {
+    pub fn new(mut save: SaveFile, conditional: &Conditional) -> Self {
+        // Compressed bodies are a different representation and need their own etag,
+        // precompressed saves come with one already
+        if let (Some(encoding), false) = (save.encoding, save.precompressed) {
+            save.etag = format!("{}-{}\"", save.etag.trim_end_matches('"'), encoding.name());
+        }
+
+        if conditional.is_not_modified(&save.etag, save.modified) {
+            SaveResponse::NotModified {
+                etag: save.etag,
+                modified: save.modified,
+                cache_control: save.cache_control,
+            }
+        } else if save.encoding.is_some() {
+            // Ranges of the compressed stream aren't known up front, so always send all of it
+            SaveResponse::File(save)
+        } else {
+            match conditional.byte_range(&save.etag, save.modified, save.size) {
+                Some(ByteRange::Satisfiable(start, end)) => {
+                    SaveResponse::Partial { save, start, end }
+                }
+                Some(ByteRange::NotSatisfiable) => {
+                    SaveResponse::RangeNotSatisfiable { size: save.size }
+                }
+                None => SaveResponse::File(save),
+            }
+        }
+    }
+
+    /// Number of uncompressed bytes of the save that are sent
+    pub fn body_size(&self) -> Option<u64> {
+        match self {
+            SaveResponse::File(save) => Some(save.size),
+            SaveResponse::Partial { start, end, .. } => Some(end - start + 1),
+            _ => None,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for SaveResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let bytes = self.body_size();
+        request.local_cache(|| SaveBytes(bytes));
+
+        let (save, range) = match self {
+            SaveResponse::File(save) => (save, None),
+            SaveResponse::Partial { save, start, end } => (save, Some((start, end))),
+            SaveResponse::RangeNotSatisfiable { size } => {
+                return Response::build()
+                    .status(Status::RangeNotSatisfiable)
+                    .header(Header::new("Content-Range", format!("bytes */{}", size)))
+                    .ok()
+            }
+            SaveResponse::NotModified {
+                etag,
+                modified,
+                cache_control,
+            } => {
+                let mut response = Response::build();
+                response
+                    .status(Status::NotModified)
+                    .header(Header::new("ETag", etag))
+                    .header(Header::new(
+                        "Last-Modified",
+                        httpdate::fmt_http_date(modified),
+                    ));
+                if let Some(cache_control) = cache_control {
+                    response.header(Header::new("Cache-Control", cache_control));
+                }
+                return response.ok();
+            }
+        };
+
+        let mut response = match (save.encoding, range) {
+            (_, Some((start, end))) => {
+                let len = end - start + 1;
+                let file = seek(save.file.take_file(), start).map_err(|e| {
+                    error!("Failed to seek in save: {}", e);
+                    Status::InternalServerError
+                })?;
+                Response::build()
+                    .status(Status::PartialContent)
+                    .header(Header::new(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, save.size),
+                    ))
+                    .header(Header::new("Content-Length", len.to_string()))
+                    .header(Header::new("Accept-Ranges", "bytes"))
+                    .streamed_body(Permitted::new(
+                        Throttled::new(file.take(len), save.throttle),
+                        save.permit,
+                    ))
+                    .finalize()
+            }
+            (None, None) => Response::build()
+                .header(Header::new("Accept-Ranges", "bytes"))
+                .sized_body(
+                    None,
+                    Permitted::new(
+                        Throttled::new(save.file.take_file(), save.throttle),
+                        save.permit,
+                    ),
+                )
+                .finalize(),
+            (Some(encoding), None) if save.precompressed => Response::build()
+                .sized_body(
+                    None,
+                    Permitted::new(
+                        Throttled::new(save.file.take_file(), save.throttle),
+                        save.permit,
+                    ),
+                )
+                .header(Header::new("Content-Encoding", encoding.name()))
+                .header(Header::new("Vary", "Accept-Encoding"))
+                .finalize(),
+            (Some(encoding), None) => {
+                let reader = BufReader::new(save.file.take_file());
+                let mut response = Response::build();
+                match encoding {
+                    Encoding::Gzip => response.streamed_body(Permitted::new(
+                        Throttled::new(GzipEncoder::new(reader), save.throttle),
+                        save.permit,
+                    )),
+                    // HTTP deflate is zlib wrapped deflate
+                    Encoding::Deflate => response.streamed_body(Permitted::new(
+                        Throttled::new(ZlibEncoder::new(reader), save.throttle),
+                        save.permit,
+                    )),
+                };
+                response
+                    .header(Header::new("Content-Encoding", encoding.name()))
+                    .header(Header::new("Vary", "Accept-Encoding"))
+                    .finalize()
+            }
+        };
+
+        response.set_header(save.content_type);
+        response.set_header(Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", save.download_name),
+        ));
+        response.set_header(Header::new("ETag", save.etag));
+        if let Some(cache_control) = save.cache_control {
+            response.set_header(Header::new("Cache-Control", cache_control));
+        }
+        response.set_header(Header::new(
+            "Last-Modified",
+            httpdate::fmt_http_date(save.modified),
+        ));
+        // Which file was picked, e.g. the autosave slot, without exposing the directory
+        response.set_header(Header::new("X-Save-Filename", header_safe(&save.file_name)));
+        if let Ok(modified) = OffsetDateTime::from(save.modified).format(&Rfc3339) {
+            response.set_header(Header::new("X-Save-Modified", modified));
+        }
+        Ok(response)
+    }
+}
+
+// Percent-encodes what isn't printable ASCII, header values can't carry other characters
+fn header_safe(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// The file was just opened and not read yet, so seeking the std file doesn't block
+fn seek(file: File, offset: u64) -> std::io::Result<File> {
+    let mut file = file
+        .try_into_std()
+        .map_err(|_| std::io::Error::other("File is busy"))?;
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(File::from_std(file))
+}
@@ -0,0 +1,118 @@
+//! Optional watcher that logs new and changed saves and invalidates cached lookups.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use tracing::{debug, error, info};
+
+use crate::save_cache::SaveCache;
+
+/// Starts watching the save directories on liftoff and stops on shutdown
+pub struct SaveWatcher {
+    save_dirs: Vec<String>,
+    file_extensions: Vec<String>,
+    recursive: bool,
+    cache: Arc<SaveCache>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl SaveWatcher {
+    pub fn new(
+        save_dirs: Vec<String>,
+        file_extensions: Vec<String>,
+        recursive: bool,
+        cache: Arc<SaveCache>,
+    ) -> Self {
+        SaveWatcher {
+            save_dirs,
+            file_extensions,
+            recursive,
+            cache,
+            watcher: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SaveWatcher {
+    fn info(&self) -> Info {
+        Info {
+            name: "Watch save directories",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, _rocket: &Rocket<Orbit>) {
+        let file_extensions = self.file_extensions.clone();
+        let cache = self.cache.clone();
+
+        let handler = move |result: notify::Result<Event>| match result {
+            Ok(event) => handle_event(event, &file_extensions, &cache),
+            Err(e) => error!("Save watcher error: {}", e),
+        };
+
+        let mut watcher = match notify::recommended_watcher(handler) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to start save watcher: {}", e);
+                return;
+            }
+        };
+
+        let mode = if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for save_dir in &self.save_dirs {
+            match watcher.watch(Path::new(save_dir), mode) {
+                Ok(()) => info!("Watching {} for new saves", save_dir),
+                Err(e) => error!("Failed to watch {}: {}", save_dir, e),
+            }
+        }
+
+        *self.watcher.lock().unwrap() = Some(watcher);
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        // Dropping the watcher stops it
+        if self.watcher.lock().unwrap().take().is_some() {
+            info!("Stopped watching save directories");
+        }
+    }
+}
+
+fn handle_event(event: Event, file_extensions: &[String], cache: &SaveCache) {
+    let is_create = matches!(event.kind, EventKind::Create(_));
+    if !is_create && !matches!(event.kind, EventKind::Modify(_) | EventKind::Remove(_)) {
+        return;
+    }
+
+    for path in &event.paths {
+        let Some(file_name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            continue;
+        };
+        // Extensions match ignoring case, like the lookups do
+        let is_save = file_extensions.iter().any(|extension| {
+            let suffix = format!(".{}", extension);
+            file_name
+                .len()
+                .checked_sub(suffix.len())
+                .and_then(|split| file_name.get(split..))
+                .is_some_and(|end| end.eq_ignore_ascii_case(&suffix))
+        });
+        if !is_save {
+            continue;
+        }
+
+        if is_create {
+            info!("New save: {}", path.display());
+        } else {
+            debug!("Save changed: {} ({:?})", path.display(), event.kind);
+        }
+        cache.invalidate_file(&file_name);
+    }
+}
@@ -0,0 +1,652 @@
+//! Tests that drive the whole server through Rocket's local client, with saves in a
+//! temporary directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use rocket::http::{Header, Status};
+use rocket::local::blocking::Client;
+use tempfile::TempDir;
+
+use crate::{build, override_value, Config};
+
+// A save directory that is deleted when the test ends
+struct Saves {
+    dir: TempDir,
+}
+
+impl Saves {
+    fn new() -> Self {
+        Saves {
+            dir: TempDir::new().expect("create temp save directory"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    // Writes a file with the given modified time in seconds since the epoch
+    fn write(&self, file_name: &str, contents: &str, modified_secs: u64) -> PathBuf {
+        let path = self.path().join(file_name);
+        write_file(&path, contents, modified_secs);
+        path
+    }
+}
+
+fn write_file(path: &Path, contents: &str, modified_secs: u64) {
+    fs::write(path, contents).expect("write save");
+    set_modified(path, modified_secs);
+}
+
+fn set_modified(path: &Path, modified_secs: u64) {
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(modified_secs)))
+        .expect("set modified time");
+}
+
+// Parses the config like config.toml, with `extra` appended to the given save directory
+fn config(save_dir: &Path, extra: &str) -> Config {
+    let toml = format!(
+        "base_url = \"http://localhost:7778\"\nport = 7778\nsave_dir = {:?}\n{}",
+        save_dir.display().to_string(),
+        extra
+    );
+    toml::from_str(&toml).expect("parse test config")
+}
+
+fn client_with(save_dir: &Path, extra: &str) -> Client {
+    let rocket = build(config(save_dir, extra), None).expect("build server");
+    Client::tracked(rocket).expect("start local client")
+}
+
+fn client(saves: &Saves) -> Client {
+    client_with(saves.path(), "")
+}
+
+// Save names on the /map page, in the order they are listed
+fn index_names(client: &Client) -> Vec<String> {
+    let response = client.get("/map").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let html = response.into_string().unwrap();
+
+    html.split("<li")
+        .skip(1)
+        .filter_map(|item| {
+            let link = item.split("</a>").next()?;
+            Some(link.rsplit('>').next()?.trim().to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn serves_latest_save() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_0.sav", "older", 1_000);
+    saves.write("Factory_autosave_1.sav", "newest", 3_000);
+    saves.write("Factory_autosave_2.sav", "middle", 2_000);
+    let client = client(&saves);
+
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "newest");
+}
+
+#[test]
+fn missing_save_is_not_found() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "save", 1_000);
+    let client = client(&saves);
+
+    let response = client.get("/map/Missing").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.into_string().unwrap().contains("\"not_found\""));
+}
+
+#[test]
+fn invalid_name_is_bad_request() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "save", 1_000);
+    let client = client(&saves);
+
+    for uri in ["/map/.Factory", "/map/%3Cscript%3E"] {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "{}", uri);
+    }
+}
+
+#[test]
+fn index_lists_save_names() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_0.sav", "a", 1_000);
+    saves.write("Factory_autosave_1.sav", "b", 2_000);
+    saves.write("Outpost.sav", "c", 3_000);
+    saves.write("notes.txt", "not a save", 4_000);
+    let client = client(&saves);
+
+    assert_eq!(index_names(&client), ["Factory", "Outpost"]);
+}
+
+#[test]
+fn equal_modified_times_pick_greatest_file_name() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_1.sav", "one", 1_000);
+    saves.write("Factory_autosave_2.sav", "two", 1_000);
+    saves.write("Factory_autosave_0.sav", "zero", 1_000);
+    // Without the cache every request sorts the directory listing again
+    let client = client_with(saves.path(), "cache_ttl_secs = 0");
+
+    for _ in 0..5 {
+        let response = client.get("/map/Factory").dispatch();
+        assert_eq!(response.into_string().unwrap(), "two");
+    }
+}
+
+#[test]
+fn index_is_sorted_case_insensitively() {
+    let saves = Saves::new();
+    for (i, name) in ["gamma", "Beta", "alpha", "Delta", "beta"]
+        .iter()
+        .enumerate()
+    {
+        saves.write(&format!("{}.sav", name), "save", 1_000 + i as u64);
+    }
+    let client = client(&saves);
+
+    // Equal names ignoring case keep a fixed order, uppercase first
+    assert_eq!(
+        index_names(&client),
+        ["alpha", "Beta", "beta", "Delta", "gamma"]
+    );
+}
+
+#[test]
+fn hidden_names_are_not_listed_but_still_served() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "factory", 1_000);
+    saves.write("Secret.sav", "secret", 2_000);
+    let client = client_with(saves.path(), "hidden_names = [\"Secret\"]");
+
+    assert_eq!(index_names(&client), ["Factory"]);
+
+    let response = client.get("/map/Secret").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "secret");
+}
+
+#[test]
+fn range_request_is_partial_content() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "0123456789", 1_000);
+    let client = client(&saves);
+
+    let response = client
+        .get("/map/Factory")
+        .header(Header::new("Range", "bytes=2-5"))
+        .dispatch();
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(
+        response.headers().get_one("Content-Range"),
+        Some("bytes 2-5/10")
+    );
+    assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+    assert_eq!(response.into_string().unwrap(), "2345");
+}
+
+#[test]
+fn error_responses_have_cors_headers() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "save", 1_000);
+    let client = client(&saves);
+
+    for (uri, status) in [
+        ("/map/Missing", Status::NotFound),
+        ("/map/.Factory", Status::BadRequest),
+    ] {
+        let response = client
+            .get(uri)
+            .header(Header::new("Origin", "https://satisfactory-calculator.com"))
+            .dispatch();
+        assert_eq!(response.status(), status, "{}", uri);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("https://satisfactory-calculator.com"),
+            "{}",
+            uri
+        );
+        assert!(
+            headers.get("Vary").any(|vary| vary.contains("Origin")),
+            "{}",
+            uri
+        );
+        assert!(headers.contains("Access-Control-Expose-Headers"), "{}", uri);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_symlinks_are_skipped() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "save", 1_000);
+    std::os::unix::fs::symlink(
+        saves.path().join("gone.sav"),
+        saves.path().join("Broken.sav"),
+    )
+    .expect("create symlink");
+    let client = client(&saves);
+
+    assert_eq!(index_names(&client), ["Factory"]);
+    let response = client.get("/map/Broken").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_served_unless_disabled() {
+    let saves = Saves::new();
+    let outside = TempDir::new().expect("create temp directory");
+    let target = outside.path().join("Linked.sav");
+    write_file(&target, "linked", 1_000);
+    std::os::unix::fs::symlink(&target, saves.path().join("Linked.sav")).expect("create symlink");
+
+    let following = client(&saves);
+    let response = following.get("/map/Linked").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "linked");
+
+    let not_following = client_with(saves.path(), "follow_symlinks = false");
+    let response = not_following.get("/map/Linked").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn save_dir_with_glob_characters() {
+    let saves = Saves::new();
+    let save_dir = saves.path().join("saves [1]*?");
+    fs::create_dir(&save_dir).expect("create save directory");
+    write_file(&save_dir.join("Factory.sav"), "save", 1_000);
+    let client = client_with(&save_dir, "");
+
+    assert_eq!(index_names(&client), ["Factory"]);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "save");
+}
+
+#[test]
+fn only_configured_extensions_are_found() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "sav", 1_000);
+    saves.write("Factory.sav.bak", "backup", 2_000);
+    saves.write("Factory.txt", "text", 3_000);
+    saves.write("Factorysav", "no extension", 4_000);
+
+    let client = client(&saves);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "sav");
+
+    let client = client_with(saves.path(), "file_extensions = [\"sav\", \"sav.bak\"]");
+    assert_eq!(index_names(&client), ["Factory"]);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "backup");
+}
+
+#[test]
+fn case_insensitive_names_fall_back_to_ignoring_case() {
+    let saves = Saves::new();
+    saves.write("factory.SAV", "lowercase", 1_000);
+
+    let client = client(&saves);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let client = client_with(saves.path(), "case_insensitive_names = true");
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "lowercase");
+
+    // An exact match wins over newer files that only match ignoring case
+    saves.write("Factory.sav", "exact", 500);
+    let client = client_with(saves.path(), "case_insensitive_names = true");
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "exact");
+}
+
+#[test]
+fn subdirectories_are_only_searched_when_recursive() {
+    let saves = Saves::new();
+    fs::create_dir(saves.path().join("old")).expect("create subdirectory");
+    saves.write("old/Factory.sav", "nested", 1_000);
+
+    let client = client(&saves);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let client = client_with(saves.path(), "recursive = true");
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "nested");
+}
+
+#[test]
+fn names_sharing_a_prefix_are_separate_saves() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_0.sav", "factory", 1_000);
+    saves.write("Factory_autosave_1.sav", "factory older", 500);
+    saves.write("FactoryTwo_autosave_0.sav", "factory two", 2_000);
+    let client = client(&saves);
+
+    assert_eq!(index_names(&client), ["Factory", "FactoryTwo"]);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "factory");
+    let response = client.get("/map/FactoryTwo").dispatch();
+    assert_eq!(response.into_string().unwrap(), "factory two");
+    // A full file stem picks that file even when it isn't the newest
+    let response = client.get("/map/Factory_autosave_1").dispatch();
+    assert_eq!(response.into_string().unwrap(), "factory older");
+}
+
+#[test]
+fn profiles_cache_their_own_lookups() {
+    let saves = Saves::new();
+    saves.write("Foo.sav", "top level", 1_000);
+    let profile_dir = saves.path().join("profile");
+    fs::create_dir(&profile_dir).expect("create profile directory");
+    write_file(&profile_dir.join("Foo.sav"), "profile", 1_000);
+    let client = client_with(
+        saves.path(),
+        &format!(
+            "[profiles.p]\nsave_dir = {:?}",
+            profile_dir.display().to_string()
+        ),
+    );
+
+    // The first lookup fills the cache, the second one would be served from it
+    for uri in ["/map/Foo", "/p/map/Foo", "/map/Foo", "/p/map/Foo"] {
+        let expected = if uri.starts_with("/p/") {
+            "profile"
+        } else {
+            "top level"
+        };
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.into_string().unwrap(), expected, "{}", uri);
+    }
+}
+
+#[test]
+fn rewritten_save_gets_a_new_etag() {
+    let saves = Saves::new();
+    let path = saves.write("Factory.sav", "before", 1_000);
+    // Keep the cached lookup, which still has the old modified time
+    let client = client_with(saves.path(), "cache_ttl_secs = 3600");
+
+    let response = client.get("/map/Factory").dispatch();
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+    // Rewritten in place with the same size, which doesn't change the directory
+    write_file(&path, "after!", 2_000);
+    let response = client
+        .get("/map/Factory")
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert_eq!(response.into_string().unwrap(), "after!");
+}
+
+#[test]
+fn names_with_spaces_are_served_and_linked_encoded() {
+    let saves = Saves::new();
+    saves.write("My Factory_autosave_0.sav", "spaces", 1_000);
+    saves.write("Bad(name).sav", "unservable", 500);
+    let client = client_with(saves.path(), "access_token = \"a b&c\"");
+
+    let response = client.get("/map?token=a%20b%26c").dispatch();
+    let html = response.into_string().unwrap();
+    assert!(
+        html.contains("/map/My%20Factory?token=a%20b%26c\""),
+        "{}",
+        html
+    );
+    assert!(!html.contains("Bad(name)"), "{}", html);
+
+    for uri in [
+        "/map/My%20Factory?token=a%20b%26c",
+        "/map/latest?token=a%20b%26c",
+    ] {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", uri);
+        assert_eq!(response.into_string().unwrap(), "spaces", "{}", uri);
+    }
+}
+
+#[test]
+fn latest_serves_the_newest_file_as_is() {
+    let saves = Saves::new();
+    saves.write("Other.sav", "other", 1_000);
+    saves.write("Factory.sav", "factory", 2_000);
+    let client = client_with(saves.path(), "[aliases]\nFactory = \"Other\"");
+
+    // Requested by name the alias applies, the newest file is still the Factory save
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "other");
+    let response = client.get("/map/latest").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "factory");
+
+    // Like on the listings, names that can't be requested are skipped
+    saves.write("Bad(name).sav", "bad name", 3_000);
+    let response = client.get("/map/latest").dispatch();
+    assert_eq!(response.into_string().unwrap(), "factory");
+}
+
+#[test]
+fn env_overrides_follow_the_option_type() {
+    let value = |key: &str, value: &str| override_value(key, value.to_string());
+
+    assert_eq!(value("port", "8080"), toml::Value::Integer(8080));
+    assert_eq!(value("watch_saves", "true"), toml::Value::Boolean(true));
+    assert_eq!(
+        value("file_extensions", "[\"sav\", \"sav.bak\"]"),
+        toml::Value::Array(vec!["sav".into(), "sav.bak".into()])
+    );
+    // Strings that read as other TOML values stay strings for options that take a string
+    assert_eq!(value("access_token", "123456"), "123456".into());
+    assert_eq!(value("index_title", "2024"), "2024".into());
+    assert_eq!(value("save_dir", "/saves"), "/saves".into());
+    assert_eq!(value("access_token", "\"quoted\""), "quoted".into());
+    // Values of the wrong type are left for the config error to report
+    assert_eq!(value("port", "abc"), "abc".into());
+}
+
+#[test]
+fn precompressed_saves_count_with_the_save_size() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", &"a".repeat(100), 1_000);
+    saves.write("Factory.sav.gz", "gzipped", 2_000);
+    let gzip = || Header::new("Accept-Encoding", "gzip");
+
+    let client = client_with(saves.path(), "enable_metrics = true");
+    let response = client.get("/map/Factory").header(gzip()).dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.into_string().unwrap(), "gzipped");
+    let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+    assert!(
+        metrics.contains("saves_bytes_served_total 100\n"),
+        "{}",
+        metrics
+    );
+
+    let client = client_with(saves.path(), "max_file_size_bytes = 50");
+    let response = client.get("/map/Factory").header(gzip()).dispatch();
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[test]
+fn precompressed_saves_keep_the_save_modified_time() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", "plain", 1_000);
+    saves.write("Factory.sav.gz", "gzipped", 2_000);
+    let client = client(&saves);
+
+    let plain = client.get("/map/Factory").dispatch();
+    let gzipped = client
+        .get("/map/Factory")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(gzipped.headers().get_one("Content-Encoding"), Some("gzip"));
+    for header in ["Last-Modified", "X-Save-Modified"] {
+        assert_eq!(
+            gzipped.headers().get_one(header),
+            plain.headers().get_one(header),
+            "{}",
+            header
+        );
+    }
+    let etag = plain.headers().get_one("ETag").unwrap();
+    assert_eq!(
+        gzipped.headers().get_one("ETag"),
+        Some(format!("{}-gz\"", etag.trim_end_matches('"')).as_str())
+    );
+}
+
+#[test]
+fn error_pages_link_back_to_the_profile_index() {
+    let saves = Saves::new();
+    let client = client_with(
+        saves.path(),
+        &format!(
+            "access_token = \"a b&c\"\n[profiles.p]\nsave_dir = {:?}",
+            saves.path().display().to_string()
+        ),
+    );
+
+    for (uri, index_url) in [
+        ("/map/Missing?token=a%20b%26c", "\"/map?token=a%20b%26c\""),
+        (
+            "/p/map/Missing?token=a%20b%26c",
+            "\"/p/map?token=a%20b%26c\"",
+        ),
+    ] {
+        let response = client
+            .get(uri)
+            .header(Header::new("Accept", "text/html"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound, "{}", uri);
+        let html = response.into_string().unwrap();
+        assert!(html.contains(index_url), "{}", html);
+    }
+}
+
+#[test]
+fn browser_redirect_keeps_the_picked_save() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_0.sav", "older", 1_000);
+    saves.write("Factory_autosave_1.sav", "newest", 2_000);
+    let client = client_with(saves.path(), "access_token = \"secret\"");
+
+    let response = client
+        .get("/map/Factory?token=secret&index=1&before=2500")
+        .header(Header::new("Accept", "text/html"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Found);
+    let location = response.headers().get_one("Location").unwrap();
+    assert!(
+        location.ends_with(
+            "url=http://localhost:7778/map/Factory?token=secret%26index=1%26before=2500"
+        ),
+        "{}",
+        location
+    );
+}
+
+#[test]
+fn cached_lookups_notice_new_saves_in_subdirectories() {
+    let saves = Saves::new();
+    let session = saves.path().join("session");
+    fs::create_dir(&session).expect("create subdirectory");
+    saves.write("session/Factory_autosave_0.sav", "older", 1_000);
+    // Saves written below are picked up by the directory's modified time changing
+    fs::File::open(&session)
+        .and_then(|dir| dir.set_modified(UNIX_EPOCH + Duration::from_secs(1_000)))
+        .expect("set directory modified time");
+    let client = client_with(saves.path(), "recursive = true\ncache_ttl_secs = 3600");
+
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "older");
+    saves.write("session/Factory_autosave_1.sav", "newest", 2_000);
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "newest");
+}
+
+#[test]
+fn routes_about_the_latest_save_skip_unstable_saves() {
+    let saves = Saves::new();
+    saves.write("Factory_autosave_0.sav", "stable", 1_000);
+    // Just written, so it may still be in progress
+    fs::write(saves.path().join("Factory_autosave_1.sav"), "writing").expect("write save");
+    let client = client_with(
+        saves.path(),
+        "serve_stable_only = true\nmin_age_secs = 3600",
+    );
+
+    let response = client.get("/map/Factory").dispatch();
+    assert_eq!(response.into_string().unwrap(), "stable");
+    let response = client.get("/map/Factory/mtime").dispatch();
+    assert_eq!(response.into_string().unwrap(), "1000");
+    let response = client.get("/map/Factory/latest.json").dispatch();
+    let latest = response.into_string().unwrap();
+    assert!(
+        latest.contains("\"filename\":\"Factory_autosave_0.sav\""),
+        "{}",
+        latest
+    );
+    let response = client.get("/map/Factory/sha256").dispatch();
+    // sha256 of "stable"
+    assert_eq!(
+        response.into_string().unwrap().trim(),
+        "f379ccb92b9116442dc65bdc35648a85d3786b34779db7f704a901fa07b00cb6"
+    );
+}
+
+#[test]
+fn empty_base_url_is_invalid() {
+    let saves = Saves::new();
+    let mut config = config(saves.path(), "");
+    assert!(config.check().is_empty(), "{:?}", config.check());
+
+    config.base_url = String::new();
+    let problems = config.check();
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].contains("base_url"), "{:?}", problems);
+}
+
+#[test]
+fn access_log_records_the_size_of_compressed_saves() {
+    let saves = Saves::new();
+    saves.write("Factory.sav", &"a".repeat(100), 1_000);
+    let log = saves.path().join("access.log");
+    let client = client_with(
+        saves.path(),
+        &format!(
+            "compress_saves = true\naccess_log = {:?}",
+            log.display().to_string()
+        ),
+    );
+
+    let response = client
+        .get("/map/Factory")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    response.into_bytes().unwrap();
+    let log = fs::read_to_string(log).expect("read access log");
+    assert!(
+        log.contains("\"GET /map/Factory HTTP/1.1\" 200 100 "),
+        "{}",
+        log
+    );
+}
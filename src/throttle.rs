@@ -0,0 +1,143 @@
+//! Bandwidth cap for save downloads, so a large download doesn't saturate the uplink.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use rocket::tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use rocket::tokio::time::{self, Instant, Sleep};
+
+// Smallest amount of bytes sent at once, so slow rates don't wake up for every byte
+const MIN_CHUNK_BYTES: f64 = 1024.0;
+
+// Clones share the active downloads, so profiles split the same rate
+#[derive(Clone)]
+pub struct DownloadThrottle {
+    bytes_per_sec: u64,
+    // Number of throttled downloads in progress, only tracked when the cap is shared
+    active: Option<Arc<AtomicUsize>>,
+}
+
+impl DownloadThrottle {
+    /// With `shared`, concurrent downloads split the rate instead of each getting all of it
+    pub fn new(bytes_per_sec: u64, shared: bool) -> Self {
+        DownloadThrottle {
+            bytes_per_sec,
+            active: shared.then(Arc::default),
+        }
+    }
+
+    /// Rate of a single download, counted as active until the slot is dropped
+    pub fn slot(&self) -> ThrottleSlot {
+        if let Some(active) = &self.active {
+            active.fetch_add(1, Ordering::Relaxed);
+        }
+        ThrottleSlot {
+            bytes_per_sec: self.bytes_per_sec,
+            active: self.active.clone(),
+        }
+    }
+}
+
+pub struct ThrottleSlot {
+    bytes_per_sec: u64,
+    active: Option<Arc<AtomicUsize>>,
+}
+
+impl ThrottleSlot {
+    fn bytes_per_sec(&self) -> f64 {
+        let downloads = self
+            .active
+            .as_ref()
+            .map_or(1, |active| active.load(Ordering::Relaxed).max(1));
+        (self.bytes_per_sec as f64 / downloads as f64).max(1.0)
+    }
+}
+
+impl Drop for ThrottleSlot {
+    fn drop(&mut self) {
+        if let Some(active) = &self.active {
+            active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Body that reads no faster than its slot allows, unthrottled without a slot
+pub struct Throttled<R> {
+    inner: R,
+    slot: Option<ThrottleSlot>,
+    // Bytes that may be read right away
+    budget: f64,
+    last_refill: Instant,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> Throttled<R> {
+    pub fn new(inner: R, slot: Option<ThrottleSlot>) -> Self {
+        Throttled {
+            inner,
+            slot,
+            budget: MIN_CHUNK_BYTES,
+            last_refill: Instant::now(),
+            sleep: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttled<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let Some(slot) = &this.slot else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+
+        // A shared rate changes as downloads start and finish, so look it up on every read
+        let bytes_per_sec = slot.bytes_per_sec();
+        // At most a tenth of a second's worth accumulates, so pauses don't turn into bursts
+        let chunk = (bytes_per_sec / 10.0).max(MIN_CHUNK_BYTES);
+        loop {
+            if let Some(sleep) = &mut this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(this.last_refill).as_secs_f64();
+            this.budget = (this.budget + elapsed * bytes_per_sec).min(chunk);
+            this.last_refill = now;
+            if this.budget >= MIN_CHUNK_BYTES {
+                break;
+            }
+
+            let wait = Duration::from_secs_f64((MIN_CHUNK_BYTES - this.budget) / bytes_per_sec);
+            this.sleep = Some(Box::pin(time::sleep(wait)));
+        }
+
+        let allowed = (this.budget as usize).min(buf.remaining());
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(allowed));
+        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        buf.advance(read);
+        this.budget -= read as f64;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for Throttled<R> {
+    fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        Pin::new(&mut self.inner).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_complete(cx)
+    }
+}